@@ -1,5 +1,25 @@
-pub mod service;
+pub mod cli;
 pub mod config;
 pub mod data;
+pub mod date;
+pub mod detect;
+pub mod download;
+pub mod edit;
+pub mod exec;
 pub mod fio;
-pub mod cli;
+pub mod git;
+pub mod host;
+pub mod journal;
+pub mod lock;
+pub mod manifest;
+pub mod oplog;
+pub mod outdated;
+pub mod parser;
+pub mod privilege;
+pub mod profile;
+pub mod scheduler;
+pub mod service;
+pub mod source;
+pub mod sync;
+pub mod verify;
+pub mod version;
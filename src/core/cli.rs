@@ -1,17 +1,320 @@
+use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
-/// A fictional versioning CLI
+use chrono::{DateTime, Utc};
+
+use color_eyre::Result as Res;
+
+use crate::core::{
+    data::Query,
+    date,
+    edit::Format,
+    profile::{self, Profiles},
+    version::VersionRange,
+};
+
+/// forget-me-not, a universal package recorder
 #[derive(Debug, Parser, PartialEq, Eq)] // requires `derive` feature
+#[command(name = "fmn")]
 pub struct Cli {
     #[command(subcommand)]
-    command: Commands,
+    pub command: Commands,
 }
 
 #[derive(Debug, Subcommand, PartialEq, Eq)]
 pub enum Commands {
-    Record { name: String },
-    Install { name: String, source: String },
+    /// record a package that is already installed
+    Record {
+        name: String,
+        /// package manager the package comes from
+        #[arg(short, long)]
+        source: Option<String>,
+        #[arg(long = "ver")]
+        version: Option<String>,
+        #[arg(short, long = "tag")]
+        tags: Vec<String>,
+        #[arg(short, long)]
+        description: Option<String>,
+        /// when it was installed, e.g. 2026-03-02, `3 weeks ago`, @1772416800
+        #[arg(short, long, value_parser = date::parse)]
+        installed: Option<DateTime<Utc>>,
+    },
+    /// install a package via a package manager, then record it
+    ///
+    /// e.g. `fmn install cargo:ripgrep@14`, which also pins it to 14, or `fmn install ripgrep cargo`
+    Install {
+        /// a source like `apt:ripgrep` or `flatpak:flathub/org.gimp.GIMP`,
+        /// or only the name when the manager follows
+        package: String,
+        manager: Option<String>,
+        /// expected sha256 of the file downloaded for a url source
+        #[arg(long)]
+        sha256: Option<String>,
+    },
+    /// uninstall a recorded package and forget it
+    Remove {
+        id: u32,
+        /// only forget the record, keep the package installed
+        #[arg(long)]
+        record_only: bool,
+    },
+    /// list packages recorded on this host, all of them unless some are selected
+    List {
+        #[command(flatten)]
+        select: Selector,
+        /// list records from every host, marked with their host
+        #[arg(long, conflicts_with = "host")]
+        all_hosts: bool,
+    },
+    /// install every recorded package
+    Apply {
+        /// max number of package managers running at the same time
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// first bring the records in line with a manifest, e.g. `fmn.toml`
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// remove recorded packages the manifest does not list
+        #[arg(long, requires = "file")]
+        prune: bool,
+        /// install exactly the versions in a lockfile, `fmn.lock` if no path is given
+        #[arg(
+            long,
+            value_name = "LOCKFILE",
+            num_args = 0..=1,
+            default_missing_value = "fmn.lock",
+            conflicts_with = "file"
+        )]
+        locked: Option<PathBuf>,
+        /// only the records of these profiles, by default the ones whose `hosts` match this host
+        #[arg(long = "profile", value_name = "PROFILE", conflicts_with_all = ["file", "locked"])]
+        profiles: Vec<String>,
+    },
+    /// write the exact version, source and checksum or commit of every recorded package
+    Lock {
+        /// where to write it
+        #[arg(short, long, default_value = "fmn.lock")]
+        output: PathBuf,
+    },
+    /// work with manifest files, which list the packages a machine should have
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommands,
+    },
+    /// show recorded packages with a newer version, all of them unless some are selected
+    Outdated {
+        #[command(flatten)]
+        select: Selector,
+    },
+    /// check that recorded installs are still in place, all of them unless some are selected
+    ///
+    /// reports missing, moved and modified files, and fails if there are any
+    Verify {
+        #[command(flatten)]
+        select: Selector,
+    },
+    /// upgrade the selected packages, pinned ones only if the newest version is within their pin
+    Upgrade {
+        #[command(flatten)]
+        select: Selector,
+        /// max number of package managers running at the same time
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    /// keep a package at a version, e.g. `fmn pin rustc "~1.82"`
+    Pin {
+        /// name or id of the record
+        record: String,
+        /// version or range to allow, the recorded version if not given
+        #[arg(value_parser = parse_range)]
+        range: Option<VersionRange>,
+    },
+    /// let a pinned package be upgraded again
+    Unpin {
+        /// name or id of the record
+        record: String,
+    },
+    /// search the packages of a manager
+    Search { manager: String, term: String },
+    /// show details of a recorded package from its manager
+    Info {
+        /// name or id of the record
+        record: String,
+    },
+    /// list what a manager has installed, marking packages that are not recorded
+    Installed { manager: String },
+    /// show which preset and configured managers are available on this host
+    Managers,
+    /// clean the caches of managers, every manager in use if none is given
+    Clean { managers: Vec<String> },
+    /// refresh the package index of managers, every manager in use if none is given
+    Refresh { managers: Vec<String> },
+    /// forget every record, packages stay installed
+    Clear,
+    /// revert the last change to the records
+    Undo {
+        /// also remove what it installed, or install again what it removed
+        #[arg(long)]
+        inverse: bool,
+    },
+    /// manage the tags of records
+    Tag {
+        #[command(subcommand)]
+        command: TagCommands,
+    },
+    /// edit a record in $EDITOR
+    Edit {
+        /// name or id of the record
+        record: String,
+        #[arg(short, long, value_enum, default_value_t)]
+        format: Format,
+        /// apply the changes without asking
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// set fields of a record, e.g. `fmn set 3 version=1.2.3 description="a tool"`
+    Set {
+        /// name or id of the record
+        record: String,
+        /// `field=value` pairs, an empty value clears the field
+        #[arg(required = true, value_parser = parse_assignment)]
+        fields: Vec<(String, String)>,
+    },
+    /// show how a recorded package changed over time
+    History { name: String },
+    /// browse the log of commands fmn ran
+    Log {
+        #[command(subcommand)]
+        command: Option<LogCommands>,
+        #[command(flatten)]
+        filter: LogFilter,
+    },
+    /// show the git repository records are synced through
+    Remote {
+        #[command(subcommand)]
+        command: Option<RemoteCommands>,
+    },
+    /// merge the records of the remote into these
+    Pull,
+    /// merge the records of the remote into these, then upload them
+    Push,
+}
+
+#[derive(Debug, Subcommand, PartialEq, Eq)]
+pub enum RemoteCommands {
+    /// sync through the git repository at `url`, a bare repository works, replaces the old remote
+    Add { url: String },
+}
+
+#[derive(Debug, Subcommand, PartialEq, Eq)]
+pub enum ManifestCommands {
+    /// write a manifest of the recorded packages, pins become version ranges
+    Generate {
+        /// where to write it, stdout if not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand, PartialEq, Eq)]
+pub enum TagCommands {
+    /// add a tag to the selected records
+    Add {
+        tag: String,
+        #[command(flatten)]
+        select: Selector,
+    },
+    /// remove a tag from the selected records
+    Rm {
+        tag: String,
+        #[command(flatten)]
+        select: Selector,
+    },
+    /// rename a tag, on every record unless some are selected
+    Rename {
+        from: String,
+        to: String,
+        #[command(flatten)]
+        select: Selector,
+    },
+    /// list all tags with how many records use them
+    List,
+}
+
+fn parse_range(s: &str) -> Result<VersionRange, String> {
+    s.parse().map_err(|e: color_eyre::Report| e.to_string())
+}
+
+fn parse_assignment(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.trim().to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected `field=value`, got `{}`", s))
+}
+
+/// selects records, every condition given must hold
+#[derive(Debug, Args, PartialEq, Eq)]
+pub struct Selector {
+    /// ids or names of records
+    pub records: Vec<String>,
+    /// records from this source
+    #[arg(long)]
+    pub source: Option<String>,
+    /// records with this tag
+    #[arg(long)]
+    pub tagged: Option<String>,
+    /// records whose version is in this range, e.g. `>=1.2, <2`
+    #[arg(long = "ver", value_parser = parse_range)]
+    pub version: Option<VersionRange>,
+    /// records from this host, by name or machine id, `*` matches any characters
+    #[arg(long)]
+    pub host: Option<String>,
+    /// records of these profiles, with the profiles they include
+    #[arg(long = "profile", value_name = "PROFILE")]
+    pub profiles: Vec<String>,
+    /// every record
+    #[arg(long)]
+    pub all: bool,
+}
+
+impl Selector {
+    /// the query it stands for, its profiles looked up in `profiles`
+    pub fn query(self, profiles: &Profiles) -> Res<Query> {
+        Ok(Query {
+            records: self.records,
+            source: self.source,
+            tag: self.tagged,
+            version: self.version,
+            host: self.host,
+            profile: match self.profiles.is_empty() {
+                true => None,
+                false => Some(profile::members(profiles, &self.profiles)?),
+            },
+            all: self.all,
+        })
+    }
+}
+
+#[derive(Debug, Subcommand, PartialEq, Eq)]
+pub enum LogCommands {
+    /// print an entry with its full output
+    Show { id: u32 },
+}
+
+#[derive(Debug, Args, PartialEq, Eq)]
+pub struct LogFilter {
+    /// only commands of this package manager
+    #[arg(short, long)]
+    pub manager: Option<String>,
+    /// only commands touching this record
+    #[arg(short, long)]
+    pub record: Option<u32>,
+    /// only failed commands
+    #[arg(short, long)]
+    pub failed: bool,
+    /// only the last n commands
+    #[arg(short = 'n', long)]
+    pub limit: Option<usize>,
 }
 
 #[cfg(test)]
@@ -23,9 +326,191 @@ mod test {
     fn test_cli() {
         let cli = Cli::parse_from(vec!["target/debug/forget-me-not", "record", "abc"]);
         let expected = Cli {
-            command: Commands::Record { name: "abc".into() },
+            command: Commands::Record {
+                name: "abc".into(),
+                source: None,
+                version: None,
+                tags: vec![],
+                description: None,
+                installed: None,
+            },
         };
 
         assert_eq!(expected, cli);
     }
+
+    #[test]
+    fn test_cli_record_installed() {
+        let cli = Cli::parse_from(["fmn", "record", "rg", "--installed", "2026-03-02T10:00:00Z"]);
+        let Commands::Record { installed, .. } = cli.command else {
+            panic!("expected record");
+        };
+        assert_eq!("2026-03-02T10:00:00+00:00", installed.unwrap().to_rfc3339());
+        assert!(Cli::try_parse_from(["fmn", "record", "rg", "--installed", "03/02/2026"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_apply_jobs() {
+        let cli = Cli::parse_from(["fmn", "apply", "-j", "3"]);
+        assert_eq!(
+            Commands::Apply {
+                jobs: Some(3),
+                file: None,
+                prune: false,
+                locked: None,
+                profiles: vec![],
+            },
+            cli.command
+        );
+        let cli = Cli::parse_from(["fmn", "apply", "-f", "fmn.toml", "--prune"]);
+        assert_eq!(
+            Commands::Apply {
+                jobs: None,
+                file: Some("fmn.toml".into()),
+                prune: true,
+                locked: None,
+                profiles: vec![],
+            },
+            cli.command
+        );
+        assert!(Cli::try_parse_from(["fmn", "apply", "--prune"]).is_err());
+        for (args, locked) in [
+            (vec!["fmn", "apply", "--locked"], "fmn.lock"),
+            (vec!["fmn", "apply", "--locked", "ci.lock"], "ci.lock"),
+        ] {
+            let Commands::Apply { locked: path, .. } = Cli::parse_from(args).command else {
+                panic!("expected apply");
+            };
+            assert_eq!(Some(PathBuf::from(locked)), path);
+        }
+        assert!(Cli::try_parse_from(["fmn", "apply", "--locked", "-f", "fmn.toml"]).is_err());
+        assert!(
+            Cli::try_parse_from(["fmn", "apply", "--profile", "work", "-f", "fmn.toml"]).is_err()
+        );
+        let Commands::Apply { profiles, .. } =
+            Cli::parse_from(["fmn", "apply", "--profile", "work", "--profile", "gaming"]).command
+        else {
+            panic!("expected apply");
+        };
+        assert_eq!(vec!["work", "gaming"], profiles);
+    }
+
+    #[test]
+    fn test_cli_tag() -> Res<()> {
+        let cli = Cli::parse_from([
+            "fmn", "tag", "add", "dev", "ripgrep", "3", "--source", "cargo",
+        ]);
+        let Commands::Tag {
+            command: TagCommands::Add { tag, select },
+        } = cli.command
+        else {
+            panic!("expected tag add");
+        };
+        assert_eq!("dev", tag);
+        assert_eq!(
+            Query {
+                records: vec!["ripgrep".into(), "3".into()],
+                source: Some("cargo".into()),
+                tag: None,
+                version: None,
+                profile: None,
+                host: None,
+                all: false,
+            },
+            select.query(&Profiles::new())?
+        );
+
+        let cli = Cli::parse_from(["fmn", "list", "--profile", "work"]);
+        let Commands::List { select, .. } = cli.command else {
+            panic!("expected list");
+        };
+        assert!(select.query(&Profiles::new()).is_err());
+        assert!(Cli::try_parse_from(["fmn", "list", "--host", "a", "--all-hosts"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_list_version() {
+        let cli = Cli::parse_from(["fmn", "list", "--ver", ">=1.2, <2"]);
+        let Commands::List { select, .. } = cli.command else {
+            panic!("expected list");
+        };
+        assert_eq!(">=1.2, <2", select.version.unwrap().to_string());
+        assert!(Cli::try_parse_from(["fmn", "list", "--ver", "^abc"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_install() {
+        let cli = Cli::parse_from(["fmn", "install", "cargo:ripgrep@14"]);
+        assert_eq!(
+            Commands::Install {
+                package: "cargo:ripgrep@14".into(),
+                manager: None,
+                sha256: None,
+            },
+            cli.command
+        );
+        let cli = Cli::parse_from(["fmn", "install", "ripgrep", "cargo"]);
+        assert_eq!(
+            Commands::Install {
+                package: "ripgrep".into(),
+                manager: Some("cargo".into()),
+                sha256: None,
+            },
+            cli.command
+        );
+    }
+
+    #[test]
+    fn test_cli_pin() {
+        let cli = Cli::parse_from(["fmn", "pin", "rustc", "~1.82"]);
+        let Commands::Pin { record, range } = cli.command else {
+            panic!("expected pin");
+        };
+        assert_eq!("rustc", record);
+        assert_eq!("~1.82", range.unwrap().to_string());
+        let cli = Cli::parse_from(["fmn", "pin", "rustc"]);
+        assert_eq!(
+            Commands::Pin {
+                record: "rustc".into(),
+                range: None
+            },
+            cli.command
+        );
+    }
+
+    #[test]
+    fn test_cli_set() {
+        let cli = Cli::parse_from(["fmn", "set", "3", "version=1.2.3", "description=a b=c"]);
+        assert_eq!(
+            Commands::Set {
+                record: "3".into(),
+                fields: vec![
+                    ("version".into(), "1.2.3".into()),
+                    ("description".into(), "a b=c".into())
+                ],
+            },
+            cli.command
+        );
+        assert!(Cli::try_parse_from(["fmn", "set", "3", "version"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_log() {
+        let cli = Cli::parse_from(["fmn", "log", "show", "12"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Log {
+                command: Some(LogCommands::Show { id: 12 }),
+                ..
+            }
+        ));
+        let cli = Cli::parse_from(["fmn", "log", "--failed", "-m", "apt"]);
+        let Commands::Log { command, filter } = cli.command else {
+            panic!("expected log");
+        };
+        assert_eq!(None, command);
+        assert!(filter.failed);
+        assert_eq!(Some("apt".into()), filter.manager);
+    }
 }
@@ -0,0 +1,637 @@
+use color_eyre::Result as Res;
+
+use crate::core::{
+    parser::OutputParser,
+    privilege::{Escalation, Privilege},
+    profile::Profiles,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+/// represents a template command
+/// e.g. install = { template = "install --user {package_name} --assumeyes" }
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct TemplateCommand {
+    pub template: String,
+    /// run another program than the manager itself,
+    /// e.g. hold = { program = "apt-mark", template = "hold {package_name}" }
+    #[serde(default)]
+    pub program: Option<String>,
+    /// how to read packages from the output, see [`OutputParser`]
+    #[serde(default)]
+    pub parse: Option<OutputParser>,
+    /// run it as another user than the other commands of the manager,
+    /// e.g. list = { template = "list", privilege = "root" }
+    #[serde(default)]
+    pub privilege: Option<Privilege>,
+}
+
+// 使用 #[serde(untagged)] 来告诉 Serde 尝试按顺序匹配每一个变体，
+// 直到成功为止，而不需要一个额外的标签字段来区分它们。
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+#[non_exhaustive]
+pub enum Command {
+    // Serde 会首先尝试将数据解析为 Inline 变体（一个表/结构体）
+    Template(TemplateCommand),
+    /// Represents a simple command, with only one String
+    /// the args will append after it with a space
+    Simple(String),
+}
+
+impl Command {
+    /// support for command format\
+    /// e.g.
+    /// package_name = "abc" and "install {package_name}" will be formatted into "install abc"
+    /// # Supported placeholders:
+    /// package_name, version (empty if unknown), remote (empty if none)
+    pub fn format(
+        &self,
+        package_name: &str,
+        version: Option<&str>,
+        remote: Option<&str>,
+    ) -> String {
+        match self {
+            Self::Template(template) => template
+                .template
+                .replace("{package_name}", package_name)
+                .replace("{version}", version.unwrap_or_default())
+                .replace("{remote}", remote.unwrap_or_default()),
+            Self::Simple(s) => {
+                format!("{} {}", s, package_name)
+            }
+        }
+    }
+
+    /// whether the command can install from a remote, i.e. it has a `{remote}`
+    pub fn takes_remote(&self) -> bool {
+        match self {
+            Self::Template(template) => template.template.contains("{remote}"),
+            Self::Simple(_) => false,
+        }
+    }
+
+    pub fn parser(&self) -> Option<&OutputParser> {
+        match self {
+            Self::Template(template) => template.parse.as_ref(),
+            Self::Simple(_) => None,
+        }
+    }
+
+    /// the privilege the command asks for itself
+    pub fn privilege(&self) -> Option<Privilege> {
+        match self {
+            Self::Template(template) => template.privilege,
+            Self::Simple(_) => None,
+        }
+    }
+
+    /// the program the command runs, `manager` unless the command names its own
+    pub fn program<'a>(&'a self, manager: &'a str) -> &'a str {
+        match self {
+            Self::Template(TemplateCommand {
+                program: Some(program),
+                ..
+            }) => program,
+            _ => manager,
+        }
+    }
+
+    /// formats the command and splits it into the argv passed to `program`,
+    /// unless the command names its own program
+    pub fn argv(
+        &self,
+        program: &str,
+        package_name: &str,
+        version: Option<&str>,
+        remote: Option<&str>,
+    ) -> Vec<String> {
+        std::iter::once(self.program(program).to_string())
+            .chain(
+                self.format(package_name, version, remote)
+                    .split_whitespace()
+                    .map(|s| s.to_string()),
+            )
+            .collect()
+    }
+}
+
+/// config for package managers, e.g. apt, dnf
+/// this should contains commands for install, upgrade, remove .etc
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct SingleManagerConfig {
+    pub install: Command,
+    pub upgrade: Command,
+    pub remove: Command,
+    /// whether the commands that change the system need root, or must never run as root,
+    /// queries like list and search run as whoever runs fmn
+    #[serde(default)]
+    pub privilege: Privilege,
+    /// command used to gain root when `privilege = "root"`,
+    /// `sudo` or `doas`, which needs `persist`
+    #[serde(default)]
+    pub escalate: Escalation,
+    /// seconds before a command is killed
+    pub timeout: Option<u64>,
+    /// how many times to retry a failed command
+    #[serde(default)]
+    pub retries: u32,
+    /// seconds to wait before the first retry, doubled after each retry
+    pub retry_delay: Option<u64>,
+    /// installs the version a record is pinned to, e.g. "install {package_name}={version}"
+    pub install_version: Option<Command>,
+    /// native hold for pinned records, e.g. { program = "apt-mark", template = "hold {package_name}" }
+    pub hold: Option<Command>,
+    /// releases the native hold when a record is unpinned
+    pub unhold: Option<Command>,
+    /// lists packages with a newer version, one per line with the name first, e.g. "outdated --verbose"
+    pub outdated: Option<Command>,
+    /// lists installed packages, read as name and version columns unless it has a parser
+    pub list: Option<Command>,
+    /// lists the files of `{package_name}`, one path per line, to find where its binary is
+    pub files: Option<Command>,
+    /// shows details of `{package_name}`
+    pub info: Option<Command>,
+    /// searches for `{package_name}`
+    pub search: Option<Command>,
+    /// removes caches and unused dependencies
+    pub clean: Option<Command>,
+    /// refreshes the package index
+    pub refresh: Option<Command>,
+}
+
+/// config for all package managers
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct ManagerConfigs {
+    #[serde(flatten)]
+    manager: HashMap<String, SingleManagerConfig>,
+}
+
+/// managers fmn knows out of the box
+const PRESETS: &str = include_str!("presets.toml");
+
+impl SingleManagerConfig {
+    /// the privilege to run `command` with, the one it asks for if any,
+    /// otherwise the manager's if it `changes` the system
+    pub fn privilege_of(&self, command: &Command, changes: bool) -> Privilege {
+        command.privilege().unwrap_or(match changes {
+            true => self.privilege,
+            false => Privilege::Auto,
+        })
+    }
+}
+
+impl ManagerConfigs {
+    /// the built-in managers, see `presets.toml`
+    pub fn presets() -> &'static Self {
+        static PRESETS_CONFIGS: OnceLock<ManagerConfigs> = OnceLock::new();
+        PRESETS_CONFIGS.get_or_init(|| toml::from_str(PRESETS).expect("valid presets"))
+    }
+
+    /// the configured manager, or the preset of the same name
+    pub fn config_of(&self, package_manager_name: &str) -> Option<&SingleManagerConfig> {
+        self.manager
+            .get(package_manager_name)
+            .or_else(|| Self::presets().manager.get(package_manager_name))
+    }
+
+    /// whether `package_manager_name` is in the config file, rather than only a preset
+    pub fn is_configured(&self, package_manager_name: &str) -> bool {
+        self.manager.contains_key(package_manager_name)
+    }
+
+    /// the configured managers and the presets, sorted by name
+    pub fn names(&self) -> BTreeSet<&str> {
+        self.manager
+            .keys()
+            .chain(Self::presets().manager.keys())
+            .map(|name| name.as_str())
+            .collect()
+    }
+}
+
+/// how git sources are built and installed
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct GitConfig {
+    /// run after the checkout, split on whitespace, see [`git::build_job`]
+    ///
+    /// [`git::build_job`]: crate::core::git::build_job
+    #[serde(default = "GitConfig::default_build")]
+    pub build: String,
+    /// where builds install to, `~/.local` by default
+    pub prefix: Option<PathBuf>,
+}
+
+impl GitConfig {
+    fn default_build() -> String {
+        "cargo install --path {checkout_dir} --root {prefix}".to_string()
+    }
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            build: Self::default_build(),
+            prefix: None,
+        }
+    }
+}
+
+/// the whole config file of fmn
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct Config {
+    #[serde(default)]
+    pub manager: ManagerConfigs,
+    /// max number of package managers running at the same time
+    pub jobs: Option<usize>,
+    /// where url sources place their binaries, `~/.local/bin` by default
+    pub bin_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub git: GitConfig,
+    /// the name of this machine in records, its hostname by default
+    pub host: Option<String>,
+    /// named sets of records, see [`Profile`]
+    ///
+    /// [`Profile`]: crate::core::profile::Profile
+    #[serde(default)]
+    pub profile: Profiles,
+}
+
+impl Config {
+    /// load the config file, falls back to the default config if it does not exist
+    pub fn load(path: &Path) -> Res<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(read_to_string(path)?.parse()?)
+    }
+}
+
+impl FromStr for Config {
+    type Err = toml::de::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_toml_parse_pmconfig() {
+        let pm_config: SingleManagerConfig = toml::from_str(
+            r#"
+install = "install"
+upgrade = { template = "upgrade -y" }
+remove = "remove""#,
+        )
+        .unwrap();
+        assert_eq!(Command::Simple("install".to_string()), pm_config.install);
+        assert_eq!(
+            Command::Template(TemplateCommand {
+                template: "upgrade -y".to_string(),
+                program: None,
+                parse: None,
+                privilege: None,
+            }),
+            pm_config.upgrade
+        );
+        assert_eq!(Command::Simple("remove".to_string()), pm_config.remove);
+    }
+
+    #[test]
+    fn test_toml_parse_config() {
+        let config: Config = r#"jobs = 3
+
+[manager.apt]
+install = "install"
+upgrade = { template = "upgrade -y" }
+remove = "remove"
+privilege = "root"
+escalate = "doas"
+timeout = 600
+retries = 2
+install_version = { template = "install {package_name}={version}" }
+hold = { program = "apt-mark", template = "hold {package_name}" }
+
+[manager.flatpak]
+install = { template = "install --user {package_name} --assumeyes" }
+upgrade = "update"
+remove = "remove"
+list = { template = "list --app --columns=application,version", parse = { columns = { name = 1, version = 2 } } }
+"#
+        .parse()
+        .unwrap();
+        assert_eq!(Some(3), config.jobs);
+        assert_eq!(
+            Some(&SingleManagerConfig {
+                install: Command::Simple("install".to_string()),
+                upgrade: Command::Template(TemplateCommand {
+                    template: "upgrade -y".to_string(),
+                    program: None,
+                    parse: None,
+                    privilege: None,
+                }),
+                remove: Command::Simple("remove".to_string()),
+                privilege: Privilege::Root,
+                escalate: Escalation::Doas,
+                timeout: Some(600),
+                retries: 2,
+                retry_delay: None,
+                install_version: Some(Command::Template(TemplateCommand {
+                    template: "install {package_name}={version}".to_string(),
+                    program: None,
+                    parse: None,
+                    privilege: None,
+                })),
+                hold: Some(Command::Template(TemplateCommand {
+                    template: "hold {package_name}".to_string(),
+                    program: Some("apt-mark".to_string()),
+                    parse: None,
+                    privilege: None,
+                })),
+                unhold: None,
+                outdated: None,
+                list: None,
+                files: None,
+                info: None,
+                search: None,
+                clean: None,
+                refresh: None,
+            }),
+            config.manager.config_of("apt")
+        );
+        assert_eq!(
+            vec![
+                "flatpak",
+                "install",
+                "--user",
+                "org.gimp.GIMP",
+                "--assumeyes"
+            ],
+            config.manager.config_of("flatpak").unwrap().install.argv(
+                "flatpak",
+                "org.gimp.GIMP",
+                None,
+                None
+            )
+        );
+        let list = config.manager.config_of("flatpak").unwrap().list.as_ref();
+        let packages = list
+            .and_then(|c| c.parser())
+            .unwrap()
+            .parse("org.gimp.GIMP 2.10.38\n")
+            .unwrap();
+        assert_eq!("org.gimp.GIMP", packages[0].name);
+        let apt = config.manager.config_of("apt").unwrap();
+        assert_eq!(
+            vec!["apt-mark", "hold", "ripgrep"],
+            apt.hold
+                .as_ref()
+                .unwrap()
+                .argv("apt", "ripgrep", None, None)
+        );
+        assert_eq!(
+            vec!["apt", "install", "ripgrep=14.1.0"],
+            apt.install_version
+                .as_ref()
+                .unwrap()
+                .argv("apt", "ripgrep", Some("14.1.0"), None)
+        );
+    }
+
+    /// what the preset parser of `manager` reads from `stdout`
+    fn preset(manager: &str, command: &str, stdout: &str) -> Vec<String> {
+        let config = ManagerConfigs::default();
+        let config = config.config_of(manager).unwrap();
+        let command = match command {
+            "outdated" => &config.outdated,
+            "list" => &config.list,
+            "search" => &config.search,
+            _ => unreachable!(),
+        };
+        command
+            .as_ref()
+            .and_then(|c| c.parser())
+            .unwrap()
+            .parse(stdout)
+            .unwrap()
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_presets() {
+        for manager in [
+            "apt", "brew", "cargo", "npm", "pip", "flatpak", "dnf", "pacman",
+        ] {
+            assert!(ManagerConfigs::default().config_of(manager).is_some());
+        }
+        let config: Config = "[manager.brew]\ninstall = \"i\"\nupgrade = \"u\"\nremove = \"r\""
+            .parse()
+            .unwrap();
+        assert_eq!(None, config.manager.config_of("brew").unwrap().list);
+        assert_eq!(None, config.manager.config_of("unknown"));
+
+        // 只有改动系统的命令才提权
+        let presets = ManagerConfigs::default();
+        let apt = presets.config_of("apt").unwrap();
+        assert_eq!(Privilege::Root, apt.privilege_of(&apt.install, true));
+        assert_eq!(
+            Privilege::Auto,
+            apt.privilege_of(apt.list.as_ref().unwrap(), false)
+        );
+        let config: Config = r#"[manager.apt]
+install = "i"
+upgrade = "u"
+remove = "r"
+privilege = "root"
+list = { template = "list", privilege = "root" }
+"#
+        .parse()
+        .unwrap();
+        let apt = config.manager.config_of("apt").unwrap();
+        assert_eq!(
+            Privilege::Root,
+            apt.privilege_of(apt.list.as_ref().unwrap(), false)
+        );
+    }
+
+    #[test]
+    fn test_preset_apt() {
+        assert_eq!(
+            vec![
+                "ripgrep 14.1.0-1 (noble,now)",
+                "libc6 2.39-0ubuntu8.3 (noble-updates,now)"
+            ],
+            preset(
+                "apt",
+                "list",
+                "Listing...\n\
+                 ripgrep/noble,now 14.1.0-1 amd64 [installed]\n\
+                 libc6/noble-updates,now 2.39-0ubuntu8.3 amd64 [installed,automatic]\n"
+            )
+        );
+        assert_eq!(
+            vec!["curl 8.5.0-2ubuntu10.5 → 8.5.0-2ubuntu10.6 (noble-security)"],
+            preset(
+                "apt",
+                "outdated",
+                "Listing...\n\
+                 curl/noble-security 8.5.0-2ubuntu10.6 amd64 [upgradable from: 8.5.0-2ubuntu10.5]\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_preset_brew() {
+        assert_eq!(
+            vec!["ripgrep 13.0.0 → 14.1.0"],
+            preset("brew", "outdated", "ripgrep (13.0.0) < 14.1.0\n")
+        );
+        assert_eq!(
+            vec!["python@3.12 3.12.4", "jq 1.7.1"],
+            preset("brew", "list", "python@3.12 3.12.3 3.12.4\njq 1.7.1\n")
+        );
+    }
+
+    #[test]
+    fn test_preset_cargo() {
+        assert_eq!(
+            vec!["ripgrep 14.1.0", "fmn 0.1.0 (/home/me/fmn)"],
+            preset(
+                "cargo",
+                "list",
+                "ripgrep v14.1.0:\n    rg\nfmn v0.1.0 (/home/me/fmn):\n    fmn\n"
+            )
+        );
+        assert_eq!(
+            vec![
+                "ripgrep 14.1.0  ripgrep is a line-oriented search tool",
+                "grep 0.3.2"
+            ],
+            preset(
+                "cargo",
+                "search",
+                "ripgrep = \"14.1.0\"    # ripgrep is a line-oriented search tool\n\
+                 grep = \"0.3.2\"\n\
+                 ... and 2048 crates more (use --limit N to see more)\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_preset_npm() {
+        assert_eq!(
+            vec!["pnpm 9.1.0", "typescript 5.4.5"],
+            preset(
+                "npm",
+                "list",
+                r#"{"name": "lib", "dependencies": {"typescript": {"version": "5.4.5", "overridden": false}, "pnpm": {"version": "9.1.0"}}}"#
+            )
+        );
+        assert_eq!(
+            vec!["ripgrep 1.0.0  a wrapper"],
+            preset(
+                "npm",
+                "search",
+                r#"[{"name": "ripgrep", "version": "1.0.0", "description": "a wrapper", "keywords": []}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_preset_pip() {
+        assert_eq!(
+            vec!["requests 2.31.0 → 2.32.3"],
+            preset(
+                "pip",
+                "outdated",
+                r#"[{"name": "requests", "version": "2.31.0", "latest_version": "2.32.3", "latest_filetype": "wheel"}]"#
+            )
+        );
+        assert_eq!(
+            vec!["black 24.4.2"],
+            preset("pip", "list", r#"[{"name": "black", "version": "24.4.2"}]"#)
+        );
+    }
+
+    #[test]
+    fn test_preset_flatpak() {
+        assert_eq!(
+            vec![
+                "org.gimp.GIMP 2.10.38 (flathub)",
+                "org.example.NoVersion (flathub)"
+            ],
+            preset(
+                "flatpak",
+                "list",
+                "org.gimp.GIMP\t2.10.38\tflathub\norg.example.NoVersion\t\tflathub\n"
+            )
+        );
+        assert_eq!(
+            vec!["org.gimp.GIMP → 3.0.0 (flathub)"],
+            preset("flatpak", "outdated", "org.gimp.GIMP\t3.0.0\tflathub\n")
+        );
+        let presets = ManagerConfigs::default();
+        let install = &presets.config_of("flatpak").unwrap().install;
+        assert!(install.takes_remote());
+        assert_eq!(
+            vec![
+                "flatpak",
+                "install",
+                "--user",
+                "--assumeyes",
+                "flathub",
+                "org.gimp.GIMP"
+            ],
+            install.argv("flatpak", "org.gimp.GIMP", None, Some("flathub"))
+        );
+        assert_eq!(
+            vec![
+                "flatpak",
+                "install",
+                "--user",
+                "--assumeyes",
+                "org.gimp.GIMP"
+            ],
+            install.argv("flatpak", "org.gimp.GIMP", None, None)
+        );
+    }
+
+    #[test]
+    fn test_preset_dnf_and_pacman() {
+        let dnf = ManagerConfigs::default();
+        assert_eq!(
+            vec![
+                "rpm",
+                "--query",
+                "--all",
+                "--queryformat",
+                r"%{NAME}\t%{VERSION}-%{RELEASE}\n"
+            ],
+            dnf.config_of("dnf")
+                .unwrap()
+                .list
+                .as_ref()
+                .unwrap()
+                .argv("dnf", "", None, None)
+        );
+        assert_eq!(
+            vec!["ripgrep 14.1.0-1.fc40"],
+            preset("dnf", "list", "ripgrep\t14.1.0-1.fc40\n")
+        );
+        assert_eq!(
+            vec!["ripgrep 14.1.0-1"],
+            preset("pacman", "list", "ripgrep 14.1.0-1\n")
+        );
+    }
+}
@@ -1,43 +1,51 @@
+pub use crate::core::version::FlexibleVersion;
+use crate::core::{date, host::Host, profile::Members, version::VersionRange};
 use chrono::{DateTime, Utc};
-use color_eyre::{Result as Res, eyre::ensure};
-use semver::Version;
+use color_eyre::{
+    Result as Res,
+    eyre::{bail, ensure, eyre},
+};
 use serde::{Deserialize, Serialize};
 use std::{
-    cell::{LazyCell, OnceCell},
-    collections::HashMap,
-    fs::{File, read_to_string},
-    path::{self, Path, PathBuf},
+    collections::{BTreeMap, HashMap},
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum FlexibleVersion {
-    Sematic(Version),
-    Raw(String),
-}
-
-impl FlexibleVersion {
-    pub fn parse(s: &str) -> Self {
-        match Version::parse(s) {
-            Ok(ver) => Self::Sematic(ver),
-            Err(_) => Self::Raw(s.to_string()),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RecordData {
     pub id: u32,
     pub name: String,
     pub version: Option<FlexibleVersion>,
+    /// versions `upgrade` and `apply` may install, set by `fmn pin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<VersionRange>,
     pub installation_date: Option<DateTime<Utc>>,
+    /// when the record last changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Utc>>,
     #[serde(serialize_with = "serialize_unix_path")]
     pub location: Option<PathBuf>,
     pub source: Option<String>,
+    /// a remote of the manager, or the url of a git or url source, see [`Source`]
+    ///
+    /// [`Source`]: crate::core::source::Source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// sha256 of the file downloaded for a url source, checked when it is downloaded again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// sha256 of the file at `location`, for files fmn placed itself, checked by `fmn verify`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
     pub tags: Vec<String>,
     /// 如果为 None，序列化时可以忽略
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// the machine it was recorded on, none for records older than hosts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<Host>,
 }
 
 /// 自定义序列化函数：将路径中的反斜杠转换为正斜杠
@@ -56,15 +64,187 @@ where
     }
 }
 
+impl std::fmt::Display for RecordData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>4}  {}", self.id, self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, " {}", version)?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, " ({})", source)?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, " [{}]", self.tags.join(", "))?;
+        }
+        if let Some(pin) = &self.pin {
+            write!(f, " pinned {}", pin)?;
+        }
+        Ok(())
+    }
+}
+
 impl RecordData {
-    pub fn validate(&self) -> Result<(), ()> {
+    pub fn validate(&self) -> Res<()> {
+        ensure!(
+            !self.name.trim().is_empty(),
+            "record name must not be empty"
+        );
+        for tag in &self.tags {
+            validate_tag(tag)?;
+        }
+        Ok(())
+    }
+
+    /// set a field from its textual form, an empty value clears an optional field
+    ///
+    /// # Supported keys:
+    /// name, version, pin (a version range), source, origin, sha256, checksum, location, description, installed, tags (comma separated),
+    /// host (its name, the rest of it stays)
+    pub fn set(&mut self, key: &str, value: &str) -> Res<()> {
+        let optional = |v: &str| (!v.is_empty()).then(|| v.to_string());
+        match key {
+            "name" => self.name = value.to_string(),
+            "version" => self.version = optional(value).map(|v| FlexibleVersion::parse(&v)),
+            "pin" => {
+                self.pin = match optional(value) {
+                    Some(v) => Some(v.parse()?),
+                    None => None,
+                }
+            }
+            "source" => self.source = optional(value),
+            "origin" => self.origin = optional(value),
+            "sha256" => self.sha256 = optional(value),
+            "checksum" => self.checksum = optional(value),
+            "location" => self.location = optional(value).map(PathBuf::from),
+            "description" => self.description = optional(value),
+            "installed" | "installation_date" => {
+                self.installation_date = match optional(value) {
+                    Some(v) => Some(date::parse(&v)?),
+                    None => None,
+                }
+            }
+            "host" => match optional(value) {
+                Some(name) => self.host.get_or_insert_with(Host::default).name = name,
+                None => self.host = None,
+            },
+            "tags" => {
+                self.tags = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(String::from)
+                    .collect()
+            }
+            key => bail!(
+                "unknown field `{}`, expected one of name, version, pin, source, origin, sha256, checksum, location, description, installed, tags, host",
+                key
+            ),
+        }
         Ok(())
     }
+
+    /// add a tag unless the record already has it, returns whether it was added
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        if self.tags.iter().any(|t| t == tag) {
+            return false;
+        }
+        self.tags.push(tag.to_string());
+        true
+    }
+
+    /// returns whether the record had the tag
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let len = self.tags.len();
+        self.tags.retain(|t| t != tag);
+        len != self.tags.len()
+    }
+}
+
+pub fn validate_tag(tag: &str) -> Res<()> {
+    ensure!(
+        !tag.is_empty() && !tag.contains(char::is_whitespace) && !tag.contains(','),
+        "invalid tag `{}`, tags must not be empty or contain spaces or commas",
+        tag
+    );
+    Ok(())
+}
+
+/// selects records, an empty query matches nothing unless `all` is set
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    /// ids or names
+    pub records: Vec<String>,
+    pub source: Option<String>,
+    pub tag: Option<String>,
+    /// only records with a recorded version in this range
+    pub version: Option<VersionRange>,
+    /// only records of these profiles
+    pub profile: Option<Members>,
+    /// only records from a host this matches, see [`Host::matches`]
+    pub host: Option<String>,
+    pub all: bool,
+}
+
+impl Query {
+    pub fn is_empty(&self) -> bool {
+        !self.all
+            && self.records.is_empty()
+            && self.source.is_none()
+            && self.tag.is_none()
+            && self.version.is_none()
+            && self.profile.is_none()
+            && self.host.is_none()
+    }
+
+    /// every condition that is set must hold
+    pub fn matches(&self, record: &RecordData) -> bool {
+        !self.is_empty()
+            && (self.records.is_empty()
+                || self
+                    .records
+                    .iter()
+                    .any(|r| *r == record.name || *r == record.id.to_string()))
+            && self
+                .source
+                .as_ref()
+                .is_none_or(|s| record.source.as_ref() == Some(s))
+            && self.tag.as_ref().is_none_or(|t| record.tags.contains(t))
+            && self
+                .version
+                .as_ref()
+                .is_none_or(|range| record.version.as_ref().is_some_and(|v| range.matches(v)))
+            && self.profile.as_ref().is_none_or(|p| p.contains(record))
+            && self
+                .host
+                .as_ref()
+                .is_none_or(|pattern| record.host.as_ref().is_some_and(|h| h.matches(pattern)))
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct DataBase {
     pub data: HashMap<u32, RecordData>,
+    /// the id of the next record, ids of removed records are never handed out again
+    next_id: u32,
+}
+
+/// the database file, older ones are only the list of records
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stored {
+    Records(Vec<RecordData>),
+    Counted {
+        #[serde(rename = "nextId")]
+        next_id: u32,
+        records: Vec<RecordData>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredRef<'a> {
+    next_id: u32,
+    records: Vec<&'a RecordData>,
 }
 
 impl DataBase {
@@ -73,39 +253,148 @@ impl DataBase {
         for d in data {
             map.insert(d.id, d);
         }
-        Self { data: map }
+        Self {
+            data: map,
+            next_id: 0,
+        }
     }
 
     pub fn from_json_db(path: &Path) -> Res<Self> {
         ensure!(path.exists(), "path does not exist");
-        let db_file = read_to_string(path)?;
-        let data: Vec<RecordData> = serde_json::from_str(&db_file)?;
-        Ok(Self::from_vec(data))
+        Self::from_json(&read_to_string(path)?)
+    }
+
+    pub fn from_json(s: &str) -> Res<Self> {
+        Ok(match serde_json::from_str(s)? {
+            Stored::Records(records) => Self::from_vec(records),
+            Stored::Counted { next_id, records } => Self {
+                next_id,
+                ..Self::from_vec(records)
+            },
+        })
+    }
+
+    /// records sorted by id, so the saved file stays stable between runs
+    pub fn to_vec(&self) -> Vec<&RecordData> {
+        let mut data: Vec<&RecordData> = self.data.values().collect();
+        data.sort_by_key(|d| d.id);
+        data
+    }
+
+    /// one field per line, so the file diffs well
+    pub fn to_json(&self) -> Res<String> {
+        let stored = StoredRef {
+            next_id: self.next_id(),
+            records: self.to_vec(),
+        };
+        Ok(serde_json::to_string_pretty(&stored)? + "\n")
+    }
+
+    pub fn to_json_db(&self, path: &Path) -> Res<()> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn next_id(&self) -> u32 {
+        let after_last = self.data.keys().max().map_or(0, |id| id + 1);
+        self.next_id.max(after_last)
+    }
+
+    /// never hand out ids below `id`, e.g. ones another host already used
+    pub fn skip_ids_below(&mut self, id: u32) {
+        self.next_id = self.next_id.max(id);
+    }
+
+    /// find a record by id, or by name if that is unambiguous
+    pub fn resolve(&self, name_or_id: &str) -> Res<&RecordData> {
+        if let Some(record) = name_or_id.parse().ok().and_then(|id| self.data.get(&id)) {
+            return Ok(record);
+        }
+        let found: Vec<&RecordData> = self
+            .to_vec()
+            .into_iter()
+            .filter(|r| r.name == name_or_id)
+            .collect();
+        match found[..] {
+            [] => Err(eyre!("no record named `{}`", name_or_id)),
+            [record] => Ok(record),
+            _ => {
+                let ids: Vec<String> = found.iter().map(|r| r.id.to_string()).collect();
+                bail!(
+                    "`{}` is ambiguous, use one of the ids {}",
+                    name_or_id,
+                    ids.join(", ")
+                )
+            }
+        }
+    }
+
+    /// ids of the records matched by `query`
+    pub fn select(&self, query: &Query) -> Vec<u32> {
+        self.to_vec()
+            .into_iter()
+            .filter(|r| query.matches(r))
+            .map(|r| r.id)
+            .collect()
+    }
+
+    /// every tag in use with the number of records carrying it
+    pub fn tag_counts(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for tag in self.data.values().flat_map(|r| &r.tags) {
+            *counts.entry(tag.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// insert a record with a fresh id, returns the id
+    pub fn insert(&mut self, mut record: RecordData) -> Res<u32> {
+        record.validate()?;
+        record.id = self.next_id();
+        let id = record.id;
+        self.next_id = id + 1;
+        self.data.insert(id, record);
+        Ok(id)
     }
 }
 
+/// the database together with the file it is stored in
 #[derive(Debug)]
 pub struct DataManager {
-    pub db: Res<DataBase>,
-    pub staged: DataBase,
+    path: PathBuf,
+    pub db: DataBase,
 }
 
 impl DataManager {
-    pub fn new(path: &Path) -> Self {
-        Self {
-            db: DataBase::from_json_db(path),
-            staged: DataBase::default(),
-        }
+    /// open the database at `path`, an absent file is treated as an empty database
+    pub fn new(path: &Path) -> Res<Self> {
+        let db = if path.exists() {
+            DataBase::from_json_db(path)?
+        } else {
+            DataBase::default()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            db,
+        })
+    }
+
+    pub fn save(&self) -> Res<()> {
+        self.db.to_json_db(&self.path)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use semver::Version;
 
     #[test]
     fn test_db_de() -> Res<()> {
-        let str = r#"{
+        let str = r#"[{
   "id": 0,
   "name": "pkg1",
   "version": {
@@ -121,9 +410,131 @@ mod test {
     "bar"
   ],
   "description": "What is this? I don't know."
-}"#;
+}]"#;
 
         let d: Vec<RecordData> = serde_json::from_str(str)?;
+        assert_eq!(1, d.len());
+        assert_eq!(
+            Some(FlexibleVersion::Semantic(Version::new(1, 21, 0))),
+            d[0].version
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_and_tags() {
+        let mut db = DataBase::default();
+        for (name, version, source, tags) in [
+            ("ripgrep", "14.1.0", "cargo", vec!["dev"]),
+            ("bat", "0.24.0", "cargo", vec!["dev", "work"]),
+            ("gimp", "2:2.10.38-1", "flatpak", vec![]),
+        ] {
+            db.insert(RecordData {
+                name: name.into(),
+                version: Some(FlexibleVersion::parse(version)),
+                source: Some(source.into()),
+                tags: tags.into_iter().map(String::from).collect(),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        assert!(db.select(&Query::default()).is_empty());
+        let by_name_or_id = Query {
+            records: vec!["ripgrep".into(), "2".into()],
+            ..Default::default()
+        };
+        assert_eq!(vec![0, 2], db.select(&by_name_or_id));
+        let cargo_work = Query {
+            source: Some("cargo".into()),
+            tag: Some("work".into()),
+            ..Default::default()
+        };
+        assert_eq!(vec![1], db.select(&cargo_work));
+        let modern = Query {
+            version: Some(">=1".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(vec![0, 2], db.select(&modern));
+        db.data
+            .get_mut(&1)
+            .unwrap()
+            .set("host", "ci-runner-1")
+            .unwrap();
+        let on_ci = Query {
+            host: Some("ci-*".into()),
+            ..Default::default()
+        };
+        assert_eq!(vec![1], db.select(&on_ci));
+
+        assert_eq!(BTreeMap::from([("dev", 2), ("work", 1)]), db.tag_counts());
+
+        let gimp = db.data.get_mut(&2).unwrap();
+        assert!(gimp.add_tag("art"));
+        assert!(!gimp.add_tag("art"));
+        assert!(gimp.remove_tag("art"));
+        assert!(!gimp.remove_tag("art"));
+        assert!(validate_tag("two words").is_err());
+    }
+
+    #[test]
+    fn test_ids_are_not_reused() -> Res<()> {
+        let mut db = DataBase::default();
+        for name in ["ripgrep", "bat"] {
+            db.insert(RecordData {
+                name: name.into(),
+                ..Default::default()
+            })?;
+        }
+        db.data.remove(&1);
+        let db = DataBase::from_json(&db.to_json()?)?;
+        assert_eq!(2, db.next_id());
+        // 旧的文件只有记录
+        let old = DataBase::from_json(
+            r#"[{"id": 4, "name": "fd", "version": null, "installationDate": null, "location": null, "source": null, "tags": []}]"#,
+        )?;
+        assert_eq!(5, old.next_id());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_and_resolve() -> Res<()> {
+        let mut db = DataBase::default();
+        for name in ["ripgrep", "bat", "bat"] {
+            db.insert(RecordData {
+                name: name.into(),
+                description: Some("something".into()),
+                ..Default::default()
+            })?;
+        }
+        assert_eq!(0, db.resolve("ripgrep")?.id);
+        assert_eq!(2, db.resolve("2")?.id);
+        assert!(db.resolve("bat").is_err());
+        assert!(db.resolve("fd").is_err());
+
+        let mut record = db.resolve("ripgrep")?.clone();
+        record.set("version", "14.1.0")?;
+        record.set("description", "")?;
+        record.set("tags", "dev, cli")?;
+        record.set("installed", "2026-03-02T10:00:00+08:00")?;
+        assert_eq!(Some(FlexibleVersion::parse("14.1.0")), record.version);
+        assert_eq!(None, record.description);
+        assert_eq!(vec!["dev", "cli"], record.tags);
+        assert_eq!(
+            "2026-03-02T02:00:00+00:00",
+            record.installation_date.unwrap().to_rfc3339()
+        );
+        record.set("pin", ">=14, <15")?;
+        assert!(record.to_string().ends_with("pinned >=14, <15"));
+        assert!(record.set("pin", "^x").is_err());
+        record.set("pin", "")?;
+        assert_eq!(None, record.pin);
+        record.host = Some(Host::current(Some("laptop")));
+        record.set("host", "desk")?;
+        assert_eq!(Host::current(Some("desk")), record.host.clone().unwrap());
+        record.set("host", "")?;
+        assert_eq!(None, record.host);
+        assert!(record.set("colour", "red").is_err());
         Ok(())
     }
 
@@ -132,15 +543,21 @@ mod test {
         let data = RecordData {
             id: 0,
             name: "pkg1".into(),
-            version: FlexibleVersion::Sematic(Version::parse("1.21.0").unwrap()).into(),
+            version: FlexibleVersion::Semantic(Version::parse("1.21.0").unwrap()).into(),
+            pin: None,
             installation_date: Utc::now().into(),
+            updated: None,
             location: PathBuf::from("/a/b/c").into(),
             source: "org.wonderland".to_string().into(),
-            tags: vec!["wtf", "rusty", "foo", "bar"]
+            origin: None,
+            sha256: None,
+            checksum: None,
+            tags: ["wtf", "rusty", "foo", "bar"]
                 .iter()
                 .map(|s| s.to_string())
                 .collect(),
             description: "What is this? I don't know.".to_string().into(),
+            host: None,
         };
         let res = serde_json::to_string_pretty(&data).unwrap();
         println!("pretty:\n{}", res);
@@ -0,0 +1,245 @@
+use chrono::{
+    DateTime, Days, Local, LocalResult, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta,
+    TimeZone, Utc,
+};
+use color_eyre::{
+    Result as Res,
+    eyre::{bail, eyre},
+};
+
+/// local date time layouts without an offset
+const LOCAL_LAYOUTS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// date time layouts carrying an offset, e.g. `2026-03-02 10:00 +0800`
+const OFFSET_LAYOUTS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S %z",
+    "%Y-%m-%d %H:%M %z",
+    "%Y-%m-%d %H:%M:%S%z",
+    "%Y-%m-%d %H:%M%z",
+];
+
+/// parse an installation date in the local time zone, see [`parse_in`]
+pub fn parse(s: &str) -> Res<DateTime<Utc>> {
+    parse_in(s, &Local::now())
+}
+
+/// parse a date given by a human, relative to `now`
+///
+/// # Supported forms:
+/// - RFC 3339, e.g. `2026-03-02T10:00:00+08:00`
+/// - date time with an offset, e.g. `2026-03-02 10:00 +0800`, or `UTC` as suffix
+/// - local date or date time, e.g. `2026-03-02`, `2026-03-02 10:00`
+/// - unix epoch in seconds, e.g. `@1772416800`
+/// - `now`, `today`, `yesterday`, `3 weeks ago`, `an hour ago`
+pub fn parse_in<Tz: TimeZone>(s: &str, now: &DateTime<Tz>) -> Res<DateTime<Utc>> {
+    let s = s.trim();
+    let lower = s.to_lowercase();
+    let tz = now.timezone();
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    for layout in OFFSET_LAYOUTS {
+        if let Ok(date) = DateTime::parse_from_str(s, layout) {
+            return Ok(date.with_timezone(&Utc));
+        }
+    }
+    if let Some(naive) = lower.strip_suffix("utc").or(lower.strip_suffix('z')) {
+        let naive = naive.trim();
+        if let Some(date) = parse_naive(naive) {
+            return Ok(date.and_utc());
+        }
+    }
+    if let Some(date) = parse_naive(s) {
+        return local(&tz, date, s);
+    }
+
+    if let Some(epoch) = s.strip_prefix('@') {
+        return from_epoch(epoch, s);
+    }
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        // 光一串数字可能是年份、日期或者时间戳，不猜
+        if s.len() == 8 {
+            bail!(
+                "`{}` is ambiguous, write the date as {}-{}-{}, or the epoch as @{}",
+                s,
+                &s[..4],
+                &s[4..6],
+                &s[6..],
+                s
+            );
+        }
+        bail!(
+            "`{}` is ambiguous, write a date as YYYY-MM-DD, or the epoch as @{}",
+            s,
+            s
+        );
+    }
+
+    if s.contains('/') {
+        bail!(
+            "`{}` is ambiguous, day and month order differ between locales, use YYYY-MM-DD",
+            s
+        );
+    }
+
+    let today = now.date_naive();
+    match lower.as_str() {
+        "now" => return Ok(now.with_timezone(&Utc)),
+        "today" => return local(&tz, today.and_time(NaiveTime::MIN), s),
+        "yesterday" => {
+            let yesterday = today - Days::new(1);
+            return local(&tz, yesterday.and_time(NaiveTime::MIN), s);
+        }
+        _ => {}
+    }
+    if let Some(ago) = lower.strip_suffix(" ago") {
+        return relative(ago, now).map(|d| d.with_timezone(&Utc));
+    }
+
+    bail!(
+        "cannot understand the date `{}`, try e.g. 2026-03-02, 2026-03-02T10:00:00Z, @1772416800 or `3 weeks ago`",
+        s
+    )
+}
+
+fn parse_naive(s: &str) -> Option<NaiveDateTime> {
+    LOCAL_LAYOUTS
+        .iter()
+        .find_map(|layout| NaiveDateTime::parse_from_str(s, layout).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .map(|d| d.and_time(NaiveTime::MIN))
+        })
+}
+
+/// a wall clock time of `tz` in UTC, fails if the clock skipped or repeated it
+fn local<Tz: TimeZone>(tz: &Tz, date: NaiveDateTime, input: &str) -> Res<DateTime<Utc>> {
+    match tz.from_local_datetime(&date) {
+        LocalResult::Single(date) => Ok(date.with_timezone(&Utc)),
+        LocalResult::Ambiguous(a, b) => bail!(
+            "`{}` is ambiguous in the local time zone, it happened twice ({} and {}), add an offset like +0100",
+            input,
+            a.with_timezone(&Utc),
+            b.with_timezone(&Utc)
+        ),
+        LocalResult::None => bail!(
+            "`{}` does not exist in the local time zone, the clock skipped it",
+            input
+        ),
+    }
+}
+
+fn from_epoch(epoch: &str, input: &str) -> Res<DateTime<Utc>> {
+    let secs: i64 = epoch
+        .parse()
+        .map_err(|_| eyre!("`{}` is not a unix epoch", input))?;
+    DateTime::from_timestamp(secs, 0).ok_or_else(|| eyre!("`{}` is out of range", input))
+}
+
+/// `3 weeks`, `an hour`, `1 day`
+fn relative<Tz: TimeZone>(s: &str, now: &DateTime<Tz>) -> Res<DateTime<Tz>> {
+    let (count, unit) = s
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| eyre!("cannot understand `{} ago`", s))?;
+    let count: u32 = match count {
+        "a" | "an" | "one" => 1,
+        n => n
+            .parse()
+            .map_err(|_| eyre!("`{}` is not a number in `{} ago`", n, s))?,
+    };
+    let unit = unit.trim();
+    let unit = unit.strip_suffix('s').unwrap_or(unit);
+    let date = match unit {
+        "second" | "sec" => now
+            .clone()
+            .checked_sub_signed(TimeDelta::seconds(count.into())),
+        "minute" | "min" => now
+            .clone()
+            .checked_sub_signed(TimeDelta::minutes(count.into())),
+        "hour" => now
+            .clone()
+            .checked_sub_signed(TimeDelta::hours(count.into())),
+        "day" => now.clone().checked_sub_days(Days::new(count.into())),
+        "week" => now
+            .clone()
+            .checked_sub_days(Days::new(u64::from(count) * 7)),
+        "month" => now.clone().checked_sub_months(Months::new(count)),
+        "year" => {
+            let months = count
+                .checked_mul(12)
+                .ok_or_else(|| eyre!("`{} ago` is out of range", s))?;
+            now.clone().checked_sub_months(Months::new(months))
+        }
+        unit => bail!(
+            "unknown unit `{}`, expected seconds, minutes, hours, days, weeks, months or years",
+            unit
+        ),
+    };
+    date.ok_or_else(|| eyre!("`{} ago` is out of range", s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn now() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2026-03-10T12:00:00+08:00").unwrap()
+    }
+
+    fn parse(s: &str) -> String {
+        parse_in(s, &now()).unwrap().to_rfc3339()
+    }
+
+    #[test]
+    fn test_absolute() {
+        assert_eq!(
+            "2026-03-02T02:00:00+00:00",
+            parse("2026-03-02T10:00:00+08:00")
+        );
+        assert_eq!("2026-03-02T10:00:00+00:00", parse("2026-03-02T10:00:00Z"));
+        assert_eq!("2026-03-02T11:30:00+00:00", parse("2026-03-02 10:30 -0100"));
+        assert_eq!("2026-03-02T10:30:00+00:00", parse("2026-03-02 10:30 UTC"));
+        // 本地时间按 +08:00 换算
+        assert_eq!("2026-03-01T16:00:00+00:00", parse("2026-03-02"));
+        assert_eq!("2026-03-02T02:30:00+00:00", parse("2026-03-02 10:30"));
+        assert_eq!("2026-03-02T02:00:00+00:00", parse("@1772416800"));
+    }
+
+    #[test]
+    fn test_relative() {
+        assert_eq!("2026-03-10T04:00:00+00:00", parse("now"));
+        assert_eq!("2026-03-09T16:00:00+00:00", parse("today"));
+        assert_eq!("2026-03-08T16:00:00+00:00", parse("yesterday"));
+        assert_eq!("2026-02-17T04:00:00+00:00", parse("3 weeks ago"));
+        assert_eq!("2026-03-10T03:00:00+00:00", parse("an hour ago"));
+        assert_eq!("2025-12-10T04:00:00+00:00", parse("3 months ago"));
+        assert_eq!("2025-03-10T04:00:00+00:00", parse("1 year ago"));
+    }
+
+    #[test]
+    fn test_ambiguous_and_invalid() {
+        for input in [
+            "03/02/2026",
+            "20260302",
+            "3 fortnights ago",
+            "last tuesday",
+            "@abc",
+            "2026",
+            "1772416800",
+            "400000000 years ago",
+        ] {
+            assert!(parse_in(input, &now()).is_err(), "{} should fail", input);
+        }
+        let err = parse_in("20260302", &now()).unwrap_err().to_string();
+        assert!(err.contains("2026-03-02"));
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use colored::Colorize;
+
+use crate::core::{config::ManagerConfigs, privilege::Privilege, version::FlexibleVersion};
+
+/// a preset or configured package manager, as found on this host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detected {
+    pub name: String,
+    /// in the config file, not only a preset
+    pub configured: bool,
+    /// where it is in PATH, `None` if it is not installed
+    pub path: Option<PathBuf>,
+    pub version: Option<FlexibleVersion>,
+    pub privilege: Privilege,
+}
+
+/// probe PATH for the program every preset and configured manager installs with
+pub fn detect(configs: &ManagerConfigs) -> Vec<Detected> {
+    configs
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let config = configs.config_of(name)?;
+            let path = which::which(config.install.program(name)).ok();
+            Some(Detected {
+                name: name.to_string(),
+                configured: configs.is_configured(name),
+                version: path.as_ref().and_then(|p| {
+                    let output = Command::new(p)
+                        .arg("--version")
+                        .stdin(Stdio::null())
+                        .stderr(Stdio::null())
+                        .output()
+                        .ok()?;
+                    version_in(&String::from_utf8_lossy(&output.stdout))
+                }),
+                path,
+                privilege: config.privilege,
+            })
+        })
+        .collect()
+}
+
+/// the first word that looks like a version in the output of `--version`,
+/// e.g. `cargo 1.79.0 (ffa9cf99a 2024-06-03)` or `Pacman v6.1.0 - libalpm v14.0.0`
+pub fn version_in(output: &str) -> Option<FlexibleVersion> {
+    output.split_whitespace().find_map(|word| {
+        let word = word.trim_end_matches([',', ')', ':']);
+        let version = word
+            .strip_prefix(['v', 'V'])
+            .filter(|w| w.starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or(word);
+        (version.starts_with(|c: char| c.is_ascii_digit()) && version.contains('.'))
+            .then(|| FlexibleVersion::parse(version))
+    })
+}
+
+/// aligned rows: name, version, privilege, path,
+/// with managers in the config file that are not found marked in red
+pub fn table(detected: &[Detected]) -> Vec<String> {
+    let versions: Vec<String> = detected
+        .iter()
+        .map(|d| {
+            d.version
+                .as_ref()
+                .map_or("-".to_string(), |v| v.to_string())
+        })
+        .collect();
+    let name_width = detected.iter().map(|d| d.name.len()).max().unwrap_or(0);
+    let version_width = versions.iter().map(|v| v.len()).max().unwrap_or(0);
+
+    detected
+        .iter()
+        .zip(versions)
+        .map(|(d, version)| {
+            let row = format!(
+                "{:<name_width$}  {:<version_width$}  {:<4}  ",
+                d.name, version, d.privilege,
+            );
+            match &d.path {
+                Some(path) => format!("✓ {}{}", row, path.display()),
+                None if d.configured => format!("✗ {}configured but not found", row)
+                    .red()
+                    .to_string(),
+                None => format!("  {}not found", row).dimmed().to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::config::Config;
+
+    #[test]
+    fn test_version_in() {
+        for (expected, output) in [
+            (Some("2.7.14"), "apt 2.7.14 (amd64)\n"),
+            (Some("1.79.0"), "cargo 1.79.0 (ffa9cf99a 2024-06-03)\n"),
+            (Some("10.5.0"), "10.5.0\n"),
+            (
+                Some("24.0"),
+                "pip 24.0 from /usr/lib/python3/dist-packages/pip (python 3.12)\n",
+            ),
+            (Some("4.3.1"), "Homebrew 4.3.1\n"),
+            (
+                Some("6.1.0"),
+                "\n .--.                  Pacman v6.1.0 - libalpm v14.0.0\n/ _.-' .-.  .-.  .-.   Copyright (C) 2006-2024\n",
+            ),
+            (None, "no version here, v or 3\n"),
+        ] {
+            assert_eq!(
+                expected.map(FlexibleVersion::parse),
+                version_in(output),
+                "{}",
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        colored::control::set_override(false);
+        let config: Config = r#"
+[manager.sh]
+install = "-c true"
+upgrade = "-c true"
+remove = "-c true"
+
+[manager.fmn-shell]
+install = { program = "sh", template = "-c true" }
+upgrade = "-c true"
+remove = "-c true"
+
+[manager.fmn-missing-manager]
+install = "install"
+upgrade = "upgrade"
+remove = "remove"
+privilege = "root"
+"#
+        .parse()
+        .unwrap();
+        let detected = detect(&config.manager);
+        let find = |name: &str| detected.iter().find(|d| d.name == name).unwrap();
+
+        assert!(find("sh").path.is_some() && find("sh").configured);
+        // 名字不是程序名时按 install 的程序找
+        assert_eq!(find("sh").path, find("fmn-shell").path);
+        let missing = find("fmn-missing-manager");
+        assert!(missing.path.is_none() && missing.configured);
+        assert_eq!(Privilege::Root, missing.privilege);
+        // 预设也会被探测，但没有写进配置
+        assert!(!find("apt").configured);
+
+        let rows = table(std::slice::from_ref(missing));
+        assert_eq!(
+            vec!["✗ fmn-missing-manager  -  root  configured but not found"],
+            rows
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    Result as Res,
+    eyre::{bail, ensure, eyre},
+};
+use sha2::{Digest, Sha256};
+
+use crate::core::scheduler::Job;
+
+/// how a downloaded file is unpacked, told by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Archive {
+    /// any tarball, `tar` detects the compression itself
+    Tar,
+    Zip,
+    /// the file is the binary
+    None,
+}
+
+impl Archive {
+    pub fn of(file_name: &str) -> Self {
+        let lower = file_name.to_lowercase();
+        if lower.ends_with(".zip") {
+            Self::Zip
+        } else if [
+            ".tar", ".tar.gz", ".tgz", ".tar.xz", ".txz", ".tar.bz2", ".tbz2", ".tar.zst",
+        ]
+        .iter()
+        .any(|ext| lower.ends_with(ext))
+        {
+            Self::Tar
+        } else {
+            Self::None
+        }
+    }
+}
+
+fn program(name: &str) -> Res<String> {
+    which::which(name).map_err(|_| {
+        eyre!(
+            "`{}` is needed for url sources, but it was not found in PATH",
+            name
+        )
+    })?;
+    Ok(name.to_string())
+}
+
+/// fetch `url` into `dest`, failing on HTTP errors and following redirects
+pub fn fetch_job(url: &str, dest: &Path) -> Res<Job> {
+    Ok(Job::new(
+        "url",
+        vec![
+            program("curl")?,
+            "--fail".into(),
+            "--location".into(),
+            "--silent".into(),
+            "--show-error".into(),
+            "--output".into(),
+            dest.to_string_lossy().into_owned(),
+            url.into(),
+        ],
+    ))
+}
+
+/// unpack `archive` into `dir`, `None` if it is not an archive
+pub fn extract_job(archive: &Path, dir: &Path) -> Res<Option<Job>> {
+    let file_name = archive.file_name().unwrap_or_default().to_string_lossy();
+    let (archive, dir) = (
+        archive.to_string_lossy().into_owned(),
+        dir.to_string_lossy().into_owned(),
+    );
+    let argv = match Archive::of(&file_name) {
+        Archive::Tar => vec![program("tar")?, "-xf".into(), archive, "-C".into(), dir],
+        Archive::Zip => vec![
+            program("unzip")?,
+            "-o".into(),
+            "-q".into(),
+            archive,
+            "-d".into(),
+            dir,
+        ],
+        Archive::None => return Ok(None),
+    };
+    Ok(Some(Job::new("url", argv)))
+}
+
+/// lowercase hex sha256 of a file
+pub fn sha256(path: &Path) -> Res<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// fails unless the sha256 of `path` is `expected`, case does not matter
+pub fn verify(path: &Path, expected: &str) -> Res<()> {
+    let actual = sha256(path)?;
+    ensure!(
+        actual.eq_ignore_ascii_case(expected.trim()),
+        "sha256 mismatch for `{}`: expected {}, got {}",
+        path.display(),
+        expected.trim(),
+        actual
+    );
+    Ok(())
+}
+
+/// a file anyone may run
+pub fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.extension().is_some_and(|e| e == "exe")
+    }
+}
+
+fn executables(dir: &Path, found: &mut Vec<PathBuf>) -> Res<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            executables(&path, found)?;
+        } else if is_executable(&path) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// the binary in an unpacked archive: the executable named `name`, or the only executable
+pub fn find_binary(dir: &Path, name: &str) -> Res<PathBuf> {
+    let mut found = Vec::new();
+    executables(dir, &mut found)?;
+    found.sort();
+    if let Some(binary) = found.iter().find(|p| {
+        p.file_stem()
+            .is_some_and(|stem| stem.to_string_lossy() == name)
+    }) {
+        return Ok(binary.clone());
+    }
+    match found.as_slice() {
+        [binary] => Ok(binary.clone()),
+        [] => bail!("no executable found in the archive of `{}`", name),
+        several => bail!(
+            "several executables in the archive of `{}`: {}, rename the record to the one to install",
+            name,
+            several
+                .iter()
+                .filter_map(|p| p.file_name())
+                .map(|n| n.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// copy `binary` into `bin_dir` as `file_name`, executable, returns where it is
+pub fn place(binary: &Path, bin_dir: &Path, file_name: &str) -> Res<PathBuf> {
+    fs::create_dir_all(bin_dir)?;
+    let dest = bin_dir.join(file_name);
+    // 先删掉旧文件，正在运行的程序也能被替换
+    if dest.exists() {
+        fs::remove_file(&dest)?;
+    }
+    fs::copy(binary, &dest)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&dest, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_archive() {
+        assert_eq!(
+            Archive::Tar,
+            Archive::of("ripgrep-14.1.0-x86_64-unknown-linux-musl.tar.gz")
+        );
+        assert_eq!(Archive::Tar, Archive::of("tool.TXZ"));
+        assert_eq!(Archive::Zip, Archive::of("tool-windows.zip"));
+        assert_eq!(Archive::None, Archive::of("jq-linux-amd64"));
+    }
+
+    #[test]
+    fn test_sha256_and_find_binary() -> Res<()> {
+        let dir = std::env::temp_dir().join(format!("fmn-download-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tool-1.0/doc"))?;
+        fs::write(dir.join("tool-1.0/doc/README"), "abc")?;
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            sha256(&dir.join("tool-1.0/doc/README"))?
+        );
+        assert!(
+            verify(
+                &dir.join("tool-1.0/doc/README"),
+                "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"
+            )
+            .is_ok()
+        );
+        assert!(verify(&dir.join("tool-1.0/doc/README"), "00").is_err());
+
+        assert!(find_binary(&dir, "tool").is_err());
+        let bin = place(
+            &dir.join("tool-1.0/doc/README"),
+            &dir.join("tool-1.0"),
+            "rg",
+        )?;
+        assert_eq!(bin, find_binary(&dir, "tool")?);
+        place(&bin, &dir.join("tool-1.0/bin"), "tool")?;
+        assert_eq!(dir.join("tool-1.0/bin/tool"), find_binary(&dir, "tool")?);
+        assert!(find_binary(&dir, "other").is_err());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    env,
+    fs::{read_to_string, remove_file, write},
+    io::{self, BufRead, Write},
+    path::Path,
+    process,
+};
+
+use color_eyre::{
+    Result as Res,
+    eyre::{bail, ensure},
+};
+use colored::Colorize;
+
+use crate::core::data::RecordData;
+
+/// how a record is presented in the editor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Toml,
+    Json,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Toml => "toml",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn render(self, record: &RecordData) -> Res<String> {
+        Ok(match self {
+            Self::Toml => toml::to_string(record)?,
+            Self::Json => serde_json::to_string_pretty(record)? + "\n",
+        })
+    }
+
+    pub fn parse(self, s: &str) -> Res<RecordData> {
+        Ok(match self {
+            Self::Toml => toml::from_str(s)?,
+            Self::Json => serde_json::from_str(s)?,
+        })
+    }
+}
+
+/// `$VISUAL`, `$EDITOR`, or vi
+fn editor() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// open `record` in the editor until it parses and validates, or the user gives up
+///
+/// returns `None` if the user gave up or did not change anything
+pub fn edit(record: &RecordData, format: Format) -> Res<Option<RecordData>> {
+    let path = env::temp_dir().join(format!(
+        "fmn-edit-{}-{}.{}",
+        record.id,
+        process::id(),
+        format.extension()
+    ));
+    write(&path, format.render(record)?)?;
+    let result = edit_file(&path, record, format);
+    // 编辑器失败也不留下临时文件
+    let _ = remove_file(&path);
+    result
+}
+
+/// the editing loop of [`edit`], on the file at `path`
+fn edit_file(path: &Path, record: &RecordData, format: Format) -> Res<Option<RecordData>> {
+    loop {
+        // 通过 sh 启动，这样 EDITOR="code --wait" 这种带参数的写法也能用
+        let status = process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor()))
+            .arg("fmn")
+            .arg(path)
+            .status()?;
+        ensure!(status.success(), "editor exited with {}", status);
+
+        let edited = format.parse(&read_to_string(path)?).and_then(|edited| {
+            ensure!(
+                edited.id == record.id,
+                "the id of a record cannot be changed"
+            );
+            edited.validate()?;
+            Ok(edited)
+        });
+        match edited {
+            Ok(edited) if edited == *record => return Ok(None),
+            Ok(edited) => return Ok(Some(edited)),
+            Err(e) => {
+                eprintln!("{} {}", "invalid record:".red(), e);
+                if !confirm("edit again?")? {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+/// ask a yes/no question on the terminal, defaults to no
+pub fn confirm(question: &str) -> Res<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        bail!("no answer, stdin is closed");
+    }
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+impl std::fmt::Display for DiffLine<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Same(l) => write!(f, "  {}", l),
+            Self::Removed(l) => write!(f, "{}", format!("- {}", l).red()),
+            Self::Added(l) => write!(f, "{}", format!("+ {}", l).green()),
+        }
+    }
+}
+
+/// line diff by longest common subsequence, fine for the size of a record
+pub fn diff_lines<'a>(before: &'a str, after: &'a str) -> Vec<DiffLine<'a>> {
+    let a: Vec<&str> = before.lines().collect();
+    let b: Vec<&str> = after.lines().collect();
+    // lcs[i][j] 是 a[i..] 和 b[j..] 的最长公共子序列长度
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push(DiffLine::Same(a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(DiffLine::Removed(a[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(b[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::data::FlexibleVersion;
+
+    #[test]
+    fn test_diff_lines() {
+        let lines = diff_lines("a\nb\nc\n", "a\nx\nc\nd\n");
+        assert_eq!(
+            vec![
+                DiffLine::Same("a"),
+                DiffLine::Removed("b"),
+                DiffLine::Added("x"),
+                DiffLine::Same("c"),
+                DiffLine::Added("d"),
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_format_round_trip() -> Res<()> {
+        let record = RecordData {
+            id: 3,
+            name: "ripgrep".into(),
+            version: Some(FlexibleVersion::parse("14.1.0")),
+            tags: vec!["dev".into()],
+            ..Default::default()
+        };
+        for format in [Format::Toml, Format::Json] {
+            assert_eq!(record, format.parse(&format.render(&record)?)?);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,369 @@
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    process::{self, Child, Stdio},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicI32, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::{
+    Result as Res,
+    eyre::{bail, eyre},
+};
+
+use crate::core::scheduler::Job;
+
+/// messages printed by managers when another process holds their lock
+const LOCK_MESSAGES: &[&str] = &[
+    // apt / dpkg
+    "Could not get lock",
+    "dpkg frontend lock",
+    "Waiting for cache lock",
+    // pacman
+    "unable to lock database",
+    // dnf / yum
+    "Waiting for process with pid",
+    "Existing lock",
+    // brew
+    "has already locked",
+    // cargo
+    "Blocking waiting for file lock",
+];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const KILL_GRACE: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// how many schedulers are running, the handler is installed while there is one
+static FORWARDING: Mutex<usize> = Mutex::new(0);
+/// process groups of running children, so that a signal handler can reach them
+static RUNNING: [AtomicI32; 64] = [const { AtomicI32::new(0) }; 64];
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    for slot in RUNNING.iter() {
+        let pgid = slot.load(Ordering::SeqCst);
+        if pgid > 0 {
+            // SAFETY: kill is async-signal-safe
+            unsafe { libc::kill(-pgid, signal) };
+        }
+    }
+}
+
+/// set `handler` for Ctrl-C and SIGTERM
+#[cfg(unix)]
+fn set_interrupt_handler(handler: libc::sighandler_t) {
+    // SAFETY: the handler only touches atomics and calls kill, or is the default one
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// while alive, Ctrl-C and SIGTERM are forwarded to the running manager processes
+/// instead of killing fmn with them, outside of it they end fmn as usual
+pub struct Forwarding(());
+
+impl Forwarding {
+    pub fn start() -> Self {
+        let mut count = FORWARDING.lock().unwrap();
+        #[cfg(unix)]
+        if *count == 0 {
+            set_interrupt_handler(on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+        *count += 1;
+        Self(())
+    }
+}
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        let mut count = FORWARDING.lock().unwrap();
+        *count -= 1;
+        #[cfg(unix)]
+        if *count == 0 {
+            set_interrupt_handler(libc::SIG_DFL);
+        }
+    }
+}
+
+/// whether fmn received Ctrl-C, no further jobs should be started after that
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// keeps the process group of a child in `RUNNING` while it is alive
+struct Registered(Option<&'static AtomicI32>);
+
+impl Registered {
+    fn new(pgid: i32) -> Self {
+        let slot = RUNNING.iter().find(|s| {
+            s.compare_exchange(0, pgid, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        });
+        Self(slot)
+    }
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0 {
+            slot.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// everything a job printed, over all of its attempts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// how a single attempt of a job ended
+#[derive(Debug, PartialEq, Eq)]
+enum Attempt {
+    Exited(Option<i32>),
+    TimedOut,
+    /// the manager complained about its lock being held, with the offending line
+    Locked(String),
+    Interrupted,
+}
+
+/// run a job, retrying with an exponential backoff as configured
+///
+/// every output line is written to `out` with a `[manager]` prefix, and captured into `output`
+pub fn run<W: Write + Send>(job: &Job, out: &Mutex<W>, output: &mut Output) -> Res<Option<i32>> {
+    let mut delay = job.retry_delay;
+    let mut attempt = 0;
+    loop {
+        if interrupted() {
+            bail!("`{}` was interrupted", job.manager);
+        }
+        let result = run_once(job, out, output)?;
+        let retryable = match &result {
+            Attempt::Exited(Some(0)) => return Ok(Some(0)),
+            Attempt::Interrupted => bail!("`{}` was interrupted", job.manager),
+            Attempt::Exited(_) | Attempt::TimedOut | Attempt::Locked(_) => true,
+        };
+        if retryable && attempt < job.retries {
+            attempt += 1;
+            let _ = writeln!(
+                out.lock().unwrap(),
+                "[{}] retrying in {:.1?} ({}/{})",
+                job.manager,
+                delay,
+                attempt,
+                job.retries
+            );
+            sleep_unless_interrupted(delay);
+            delay = (delay * 2).min(MAX_BACKOFF);
+            continue;
+        }
+        return match result {
+            Attempt::Exited(code) => Ok(code),
+            Attempt::TimedOut => bail!(
+                "`{}` timed out after {:?}",
+                job.argv.join(" "),
+                job.timeout.unwrap_or_default()
+            ),
+            Attempt::Locked(line) => bail!(
+                "`{}` is locked by another process, try again once it finishes\n  {}",
+                job.manager,
+                line
+            ),
+            Attempt::Interrupted => unreachable!(),
+        };
+    }
+}
+
+fn sleep_unless_interrupted(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !interrupted() && Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL.min(deadline - Instant::now()));
+    }
+}
+
+fn run_once<W: Write + Send>(job: &Job, out: &Mutex<W>, output: &mut Output) -> Res<Attempt> {
+    let (program, args) = job
+        .argv
+        .split_first()
+        .ok_or_else(|| eyre!("empty command for `{}`", job.manager))?;
+    let mut command = process::Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // 独立的进程组，终端的 Ctrl-C 只会发给 fmn，由 fmn 转发
+    #[cfg(unix)]
+    command.process_group(0);
+    let mut child = command
+        .spawn()
+        .map_err(|e| eyre!("failed to spawn `{}`: {}", program, e))?;
+    let _registered = Registered::new(child.id() as i32);
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let lock_line = Mutex::new(None);
+    let finished = thread::scope(|s| {
+        let err = s.spawn(|| forward_lines(&job.manager, stderr, out, &lock_line));
+        let std = s.spawn(|| forward_lines(&job.manager, stdout, out, &lock_line));
+        let finished = wait(&mut child, job.timeout);
+        output.stderr += &err.join().expect("reader thread panicked");
+        output.stdout += &std.join().expect("reader thread panicked");
+        finished
+    })?;
+
+    Ok(match finished {
+        _ if interrupted() => Attempt::Interrupted,
+        None => Attempt::TimedOut,
+        Some(code) => match lock_line.into_inner().unwrap() {
+            Some(line) if code != Some(0) => Attempt::Locked(line),
+            _ => Attempt::Exited(code),
+        },
+    })
+}
+
+/// wait for the child to exit, kills its process group on timeout or interruption
+///
+/// returns `None` if the child had to be killed
+fn wait(child: &mut Child, timeout: Option<Duration>) -> Res<Option<Option<i32>>> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status.code()));
+        }
+        if interrupted() {
+            // 信号处理函数已经转发了 SIGINT，给它一点时间收尾
+            terminate(child, KILL_GRACE)?;
+            return Ok(None);
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            kill_group(child, false);
+            terminate(child, KILL_GRACE)?;
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// wait up to `grace` for the child to exit, then kill whatever is left of its process group
+fn terminate(child: &mut Child, grace: Duration) -> Res<()> {
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline && child.try_wait()?.is_none() {
+        thread::sleep(POLL_INTERVAL);
+    }
+    kill_group(child, true);
+    child.wait()?;
+    Ok(())
+}
+
+/// send SIGTERM to the process group of the child, or SIGKILL if `force`
+#[cfg(unix)]
+fn kill_group(child: &mut Child, force: bool) {
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    // SAFETY: the child is the leader of its own process group
+    unsafe { libc::kill(-(child.id() as i32), signal) };
+}
+
+/// without process groups only the child itself can be killed
+#[cfg(not(unix))]
+fn kill_group(child: &mut Child, _force: bool) {
+    let _ = child.kill();
+}
+
+/// forward every line to `out`, returns everything that was read
+fn forward_lines<R: Read, W: Write>(
+    prefix: &str,
+    reader: R,
+    out: &Mutex<W>,
+    lock_line: &Mutex<Option<String>>,
+) -> String {
+    let mut captured = String::new();
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else { break };
+        if LOCK_MESSAGES.iter().any(|m| line.contains(m)) {
+            lock_line
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| line.clone());
+        }
+        captured.push_str(&line);
+        captured.push('\n');
+        let mut out = out.lock().unwrap();
+        let _ = writeln!(out, "[{}] {}", prefix, line);
+    }
+    captured
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sh(script: &str) -> Job {
+        Job::new("apt", vec!["sh".into(), "-c".into(), script.into()])
+    }
+
+    #[test]
+    fn test_timeout_kills_process_group() {
+        let mut job = sh("sleep 5 & sleep 5");
+        job.timeout = Some(Duration::from_millis(200));
+        let start = Instant::now();
+        let err = run(&job, &Mutex::new(std::io::sink()), &mut Output::default()).unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+        assert!(start.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_retries_until_success() {
+        let dir = std::env::temp_dir().join(format!("fmn-retry-{}", std::process::id()));
+        let _ = std::fs::remove_file(&dir);
+        let mut job = sh(&format!(
+            "if [ -e {0} ]; then exit 0; else touch {0}; exit 1; fi",
+            dir.display()
+        ));
+        job.retries = 2;
+        job.retry_delay = Duration::from_millis(10);
+        let mut out = Vec::new();
+        let mut output = Output::default();
+        assert_eq!(
+            Some(0),
+            run(&job, &Mutex::new(&mut out), &mut output).unwrap()
+        );
+        assert!(String::from_utf8(out).unwrap().contains("[apt] retrying"));
+        assert_eq!("", output.stderr);
+        std::fs::remove_file(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_exit_code_after_retries() {
+        let mut job = sh("exit 4");
+        job.retries = 1;
+        job.retry_delay = Duration::from_millis(10);
+        assert_eq!(
+            Some(4),
+            run(&job, &Mutex::new(std::io::sink()), &mut Output::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_lock_held_is_reported() {
+        let job = sh(
+            "echo 'E: Could not get lock /var/lib/dpkg/lock-frontend. It is held by process 42' >&2; exit 100",
+        );
+        let err = run(&job, &Mutex::new(std::io::sink()), &mut Output::default()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("`apt` is locked by another process")
+        );
+        assert!(err.to_string().contains("/var/lib/dpkg/lock-frontend"));
+    }
+}
@@ -1,24 +1,41 @@
 use std::path::PathBuf;
 
 use color_eyre::Result as Res;
+use etcetera::app_strategy::{AppStrategy, AppStrategyArgs, Xdg};
 
-// pub fn get_config_path() -> Res<PathBuf>{
-//     use etcetera::app_strategy::{AppStrategy, AppStrategyArgs, Xdg};
-
-//         // 1. 定义应用策略参数
-//         let args = AppStrategyArgs {
-//             top_level_domain: "com".to_string(), // 这俩都仅在某些策略中用到，应该不用担心
-//             author: "fmn_author".to_string(),
-//             app_name: "fmn".to_string(),
-//         };
-
-//         // 2. 显式使用 Xdg 策略（确保在 macOS 上使用 ~/.config/fmn）
-//         let config_path = Xdg::new(args)
-//             .ok()
-//             .map(|strategy| strategy.config_dir().join("config.toml"));
-
-//         if let Some(path) = config_path.as_ref().filter(|p| p.exists()) {
-//             return Ok(path.to_path_buf());
-//         }
-    
-// }
\ No newline at end of file
+const CONFIG_FILE_NAME: &str = "config.toml";
+const DATABASE_FILE_NAME: &str = "records.json";
+const OPLOG_DIR_NAME: &str = "oplog";
+const SYNC_DIR_NAME: &str = "sync";
+
+fn strategy() -> Res<Xdg> {
+    // 1. 定义应用策略参数
+    let args = AppStrategyArgs {
+        top_level_domain: "com".to_string(), // 这俩都仅在某些策略中用到，应该不用担心
+        author: "fmn_author".to_string(),
+        app_name: "fmn".to_string(),
+    };
+
+    // 2. 显式使用 Xdg 策略（确保在 macOS 上使用 ~/.config/fmn）
+    Ok(Xdg::new(args)?)
+}
+
+/// path of the config file, e.g. `~/.config/fmn/config.toml`
+pub fn get_config_path() -> Res<PathBuf> {
+    Ok(strategy()?.config_dir().join(CONFIG_FILE_NAME))
+}
+
+/// path of the json record database, e.g. `~/.local/share/fmn/records.json`
+pub fn get_db_path() -> Res<PathBuf> {
+    Ok(strategy()?.data_dir().join(DATABASE_FILE_NAME))
+}
+
+/// directory of the operation log, e.g. `~/.local/share/fmn/oplog`
+pub fn get_oplog_dir() -> Res<PathBuf> {
+    Ok(strategy()?.data_dir().join(OPLOG_DIR_NAME))
+}
+
+/// the git repository records are synced through, e.g. `~/.local/share/fmn/sync`
+pub fn get_sync_dir() -> Res<PathBuf> {
+    Ok(strategy()?.data_dir().join(SYNC_DIR_NAME))
+}
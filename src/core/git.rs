@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use color_eyre::{Result as Res, eyre::eyre};
+
+use crate::core::{scheduler::Job, source};
+
+fn git() -> Res<String> {
+    which::which("git").map_err(|_| {
+        eyre!("`git` is needed for git sources and sync, but it was not found in PATH")
+    })?;
+    Ok("git".to_string())
+}
+
+fn path(p: &Path) -> String {
+    p.to_string_lossy().into_owned()
+}
+
+/// clone `url` into `dir`, without checking out a branch yet
+pub fn clone_job(url: &str, dir: &Path) -> Res<Job> {
+    Ok(Job::new(
+        source::GIT,
+        vec![
+            git()?,
+            "clone".into(),
+            "--quiet".into(),
+            "--no-checkout".into(),
+            url.into(),
+            path(dir),
+        ],
+    ))
+}
+
+/// check out a tag, branch or commit, the default branch if `None`
+pub fn checkout_job(dir: &Path, reference: Option<&str>) -> Res<Job> {
+    Ok(Job::new(
+        source::GIT,
+        vec![
+            git()?,
+            "-C".into(),
+            path(dir),
+            "checkout".into(),
+            "--quiet".into(),
+            "--detach".into(),
+            reference.unwrap_or("HEAD").into(),
+        ],
+    ))
+}
+
+/// prints the full hash of the commit checked out
+pub fn rev_parse_job(dir: &Path) -> Res<Job> {
+    Ok(Job::new(
+        source::GIT,
+        vec![
+            git()?,
+            "-C".into(),
+            path(dir),
+            "rev-parse".into(),
+            "HEAD".into(),
+        ],
+    ))
+}
+
+/// git running `args` in the repository `dir`
+pub fn job(dir: &Path, args: &[&str]) -> Res<Job> {
+    let mut argv = vec![git()?, "-C".into(), path(dir)];
+    argv.extend(args.iter().map(|arg| arg.to_string()));
+    Ok(Job::new(source::GIT, argv))
+}
+
+/// the build command, split on whitespace before the placeholders are filled in,
+/// so paths with spaces stay one argument
+///
+/// # Supported placeholders:
+/// checkout_dir, prefix, package_name
+pub fn build_job(template: &str, checkout_dir: &Path, prefix: &Path, package_name: &str) -> Job {
+    let argv = template
+        .split_whitespace()
+        .map(|arg| {
+            arg.replace("{checkout_dir}", &path(checkout_dir))
+                .replace("{prefix}", &path(prefix))
+                .replace("{package_name}", package_name)
+        })
+        .collect();
+    Job::new(source::GIT, argv)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_job() {
+        let job = build_job(
+            "make -C {checkout_dir} install PREFIX={prefix}",
+            Path::new("/tmp/my tool"),
+            Path::new("/home/me/.local"),
+            "tool",
+        );
+        assert_eq!(
+            vec![
+                "make",
+                "-C",
+                "/tmp/my tool",
+                "install",
+                "PREFIX=/home/me/.local"
+            ],
+            job.argv
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::fs::read_to_string;
+
+use serde::{Deserialize, Serialize};
+
+/// the machine a record was installed on
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Host {
+    /// the name set in the config, or the hostname
+    pub name: String,
+    /// stays the same when the machine is renamed, only on linux
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine_id: Option<String>,
+    /// see [`os`]
+    pub os: String,
+    /// e.g. `ubuntu 24.04`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distro: Option<String>,
+}
+
+impl Host {
+    /// this machine, called `name` if one is configured
+    pub fn current(name: Option<&str>) -> Self {
+        Self {
+            name: name.map_or_else(hostname, String::from),
+            machine_id: machine_id(),
+            os: os().to_string(),
+            distro: read_to_string("/etc/os-release")
+                .ok()
+                .and_then(|s| distro(&s)),
+        }
+    }
+
+    /// whether both are the same machine, by machine id if both have one, otherwise by name
+    pub fn is(&self, other: &Host) -> bool {
+        match (&self.machine_id, &other.machine_id) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.name == other.name,
+        }
+    }
+
+    /// whether `pattern` matches the name, or is the machine id
+    pub fn matches(&self, pattern: &str) -> bool {
+        matches(pattern, &self.name) || self.machine_id.as_deref() == Some(pattern)
+    }
+}
+
+impl std::fmt::Display for Host {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}", self.name, self.os)?;
+        if let Some(distro) = &self.distro {
+            write!(f, ", {}", distro)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// the machine id of systemd or dbus, if there is one
+fn machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .filter_map(|path| read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+        .find(|id| !id.is_empty())
+}
+
+/// `ID VERSION_ID` of an `os-release` file, e.g. `ubuntu 24.04`
+fn distro(os_release: &str) -> Option<String> {
+    let field = |key: &str| {
+        os_release.lines().find_map(|line| {
+            let value = line.trim().strip_prefix(key)?.strip_prefix('=')?;
+            Some(value.trim_matches(['"', '\'']).to_string())
+        })
+    };
+    let id = field("ID").filter(|id| !id.is_empty())?;
+    Some(match field("VERSION_ID") {
+        Some(version) if !version.is_empty() => format!("{} {}", id, version),
+        _ => id,
+    })
+}
+
+/// the name of this machine, empty if it cannot be found
+pub fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        // SAFETY: the buffer is valid for its whole length, gethostname truncates to it
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } == 0 {
+            let end = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+            return String::from_utf8_lossy(&buf[..end]).into_owned();
+        }
+    }
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_default()
+}
+
+/// the operating system, as in `std::env::consts::OS`, e.g. `linux`, `macos`
+pub fn os() -> &'static str {
+    std::env::consts::OS
+}
+
+/// whether `name` matches `pattern`, where `*` matches any characters
+pub fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            // 依次尝试 * 吞掉的长度
+            name.char_indices()
+                .map(|(i, _)| i)
+                .chain([name.len()])
+                .any(|i| matches(rest, &name[i..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        for (pattern, name, expected) in [
+            ("laptop", "laptop", true),
+            ("laptop", "laptop2", false),
+            ("ci-*", "ci-runner-3", true),
+            ("ci-*", "ci", false),
+            ("*", "", true),
+            ("*-dev-*", "my-dev-box", true),
+            ("*.local", "mac.lan", false),
+        ] {
+            assert_eq!(expected, matches(pattern, name), "{} {}", pattern, name);
+        }
+        assert_eq!(hostname(), hostname().trim());
+    }
+
+    #[test]
+    fn test_host() {
+        assert_eq!(
+            Some("ubuntu 24.04".to_string()),
+            distro("NAME=\"Ubuntu\"\nVERSION_ID=\"24.04\"\nID=ubuntu\nID_LIKE=debian\n")
+        );
+        assert_eq!(
+            Some("arch".to_string()),
+            distro("ID=arch\nBUILD_ID=rolling")
+        );
+        assert_eq!(None, distro("NAME=Linux"));
+
+        let host = |name: &str, id: Option<&str>| Host {
+            name: name.into(),
+            machine_id: id.map(String::from),
+            os: "linux".into(),
+            distro: None,
+        };
+        // 改了名字的同一台机器
+        assert!(host("laptop", Some("ab12")).is(&host("old-laptop", Some("ab12"))));
+        assert!(!host("laptop", Some("ab12")).is(&host("laptop", Some("cd34"))));
+        assert!(host("laptop", None).is(&host("laptop", Some("cd34"))));
+        assert!(host("ci-runner-1", Some("ab12")).matches("ci-*"));
+        assert!(host("laptop", Some("ab12")).matches("ab12"));
+        assert_eq!("laptop (linux)", host("laptop", None).to_string());
+
+        let here = Host::current(Some("fmn-test"));
+        assert_eq!("fmn-test", here.name);
+        assert_eq!(os(), here.os);
+        assert!(Host::current(None).is(&Host::current(None)));
+    }
+}
@@ -0,0 +1,462 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::{OpenOptions, create_dir_all, read_to_string, write},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use color_eyre::Result as Res;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    data::{FlexibleVersion, RecordData},
+    version::VersionRange,
+};
+
+const JOURNAL_FILE_NAME: &str = "changes.jsonl";
+
+/// a record before and after a change, `None` means it did not exist
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordChange {
+    pub id: u32,
+    pub before: Option<RecordData>,
+    pub after: Option<RecordData>,
+}
+
+/// every record change made by one fmn command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    pub id: u32,
+    pub timestamp: DateTime<Utc>,
+    /// the command that caused the changes, e.g. `remove 3`
+    pub command: String,
+    /// ids of the operation log entries run by the command
+    pub operations: Vec<u32>,
+    pub changes: Vec<RecordChange>,
+    /// set if this transaction reverted another one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<u32>,
+}
+
+/// one thing that happened to a record, derived from a [`RecordChange`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Created,
+    Deleted,
+    Renamed(String, String),
+    Version(Option<FlexibleVersion>, Option<FlexibleVersion>),
+    Pin(Option<VersionRange>, Option<VersionRange>),
+    Source(Option<String>, Option<String>),
+    Origin(Option<String>, Option<String>),
+    Location(Option<PathBuf>, Option<PathBuf>),
+    Tags {
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    Description,
+    InstallationDate,
+    Sha256,
+    Checksum,
+    Host(Option<String>, Option<String>),
+}
+
+fn or_none<T: std::fmt::Display>(value: &Option<T>) -> String {
+    value.as_ref().map_or("none".to_string(), |v| v.to_string())
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Created => write!(f, "created"),
+            Self::Deleted => write!(f, "deleted"),
+            Self::Renamed(from, to) => write!(f, "renamed {} → {}", from, to),
+            Self::Version(from, to) => write!(f, "version {} → {}", or_none(from), or_none(to)),
+            Self::Pin(from, to) => write!(f, "pin {} → {}", or_none(from), or_none(to)),
+            Self::Source(from, to) => write!(f, "source {} → {}", or_none(from), or_none(to)),
+            Self::Origin(from, to) => write!(f, "origin {} → {}", or_none(from), or_none(to)),
+            Self::Location(from, to) => write!(
+                f,
+                "location {} → {}",
+                or_none(&from.as_ref().map(|p| p.display())),
+                or_none(&to.as_ref().map(|p| p.display()))
+            ),
+            Self::Tags { added, removed } => {
+                write!(f, "tags")?;
+                for tag in added {
+                    write!(f, " +{}", tag)?;
+                }
+                for tag in removed {
+                    write!(f, " -{}", tag)?;
+                }
+                Ok(())
+            }
+            Self::Description => write!(f, "description changed"),
+            Self::InstallationDate => write!(f, "installation date changed"),
+            Self::Sha256 => write!(f, "sha256 changed"),
+            Self::Checksum => write!(f, "checksum changed"),
+            Self::Host(from, to) => write!(f, "host {} → {}", or_none(from), or_none(to)),
+        }
+    }
+}
+
+impl RecordChange {
+    pub fn events(&self) -> Vec<Event> {
+        let (before, after) = match (&self.before, &self.after) {
+            (None, None) => return Vec::new(),
+            (None, Some(_)) => return vec![Event::Created],
+            (Some(_), None) => return vec![Event::Deleted],
+            (Some(b), Some(a)) => (b, a),
+        };
+        let mut events = Vec::new();
+        if before.name != after.name {
+            events.push(Event::Renamed(before.name.clone(), after.name.clone()));
+        }
+        if before.version != after.version {
+            events.push(Event::Version(
+                before.version.clone(),
+                after.version.clone(),
+            ));
+        }
+        if before.pin != after.pin {
+            events.push(Event::Pin(before.pin.clone(), after.pin.clone()));
+        }
+        if before.source != after.source {
+            events.push(Event::Source(before.source.clone(), after.source.clone()));
+        }
+        if before.origin != after.origin {
+            events.push(Event::Origin(before.origin.clone(), after.origin.clone()));
+        }
+        if before.location != after.location {
+            events.push(Event::Location(
+                before.location.clone(),
+                after.location.clone(),
+            ));
+        }
+        if before.tags != after.tags {
+            let diff =
+                |a: &[String], b: &[String]| a.iter().filter(|t| !b.contains(t)).cloned().collect();
+            events.push(Event::Tags {
+                added: diff(&after.tags, &before.tags),
+                removed: diff(&before.tags, &after.tags),
+            });
+        }
+        if before.description != after.description {
+            events.push(Event::Description);
+        }
+        if before.installation_date != after.installation_date {
+            events.push(Event::InstallationDate);
+        }
+        if before.sha256 != after.sha256 {
+            events.push(Event::Sha256);
+        }
+        if before.checksum != after.checksum {
+            events.push(Event::Checksum);
+        }
+        if before.host != after.host {
+            let name = |r: &RecordData| r.host.as_ref().map(|h| h.name.clone());
+            events.push(Event::Host(name(before), name(after)));
+        }
+        events
+    }
+
+    /// whether the record was called `name` before or after the change
+    pub fn concerns(&self, name: &str) -> bool {
+        [&self.before, &self.after]
+            .into_iter()
+            .flatten()
+            .any(|r| r.name == name)
+    }
+}
+
+/// an event of a record, together with when and why it happened
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub command: String,
+    pub record: u32,
+    pub name: String,
+    pub event: Event,
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}  {:>4}  {}  {}  via fmn {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.record,
+            self.name,
+            self.event,
+            self.command
+        )
+    }
+}
+
+/// compare two versions of the database, changes are sorted by record id
+pub fn diff(
+    before: &HashMap<u32, RecordData>,
+    after: &HashMap<u32, RecordData>,
+) -> Vec<RecordChange> {
+    let ids: BTreeSet<u32> = before.keys().chain(after.keys()).copied().collect();
+    ids.into_iter()
+        .filter_map(|id| {
+            let (b, a) = (before.get(&id), after.get(&id));
+            (b != a).then(|| RecordChange {
+                id,
+                before: b.cloned(),
+                after: a.cloned(),
+            })
+        })
+        .collect()
+}
+
+/// append-only journal of record changes, with the before-images needed by `undo`
+#[derive(Debug)]
+pub struct Journal {
+    dir: PathBuf,
+}
+
+impl Journal {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(JOURNAL_FILE_NAME)
+    }
+
+    pub fn transactions(&self) -> Res<Vec<Transaction>> {
+        let path = self.path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        read_to_string(path)?
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| Ok(serde_json::from_str(l)?))
+            .collect()
+    }
+
+    /// append a transaction, its id is assigned here
+    pub fn append(&self, mut transaction: Transaction) -> Res<u32> {
+        create_dir_all(&self.dir)?;
+        transaction.id = self.transactions()?.last().map_or(0, |t| t.id + 1);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path())?;
+        writeln!(file, "{}", serde_json::to_string(&transaction)?)?;
+        Ok(transaction.id)
+    }
+
+    /// move the changes of record `from` to `to`, when a sync gave `from` to another record
+    pub fn renumber(&self, from: u32, to: u32) -> Res<()> {
+        let path = self.path();
+        if !path.exists() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for mut transaction in self.transactions()? {
+            for change in transaction.changes.iter_mut().filter(|c| c.id == from) {
+                change.id = to;
+                for record in change.before.iter_mut().chain(change.after.iter_mut()) {
+                    record.id = to;
+                }
+            }
+            lines += &(serde_json::to_string(&transaction)? + "\n");
+        }
+        write(path, lines)?;
+        Ok(())
+    }
+
+    /// everything that happened to records that were ever called `name`, oldest first
+    pub fn history(&self, name: &str) -> Res<Vec<HistoryEntry>> {
+        let transactions = self.transactions()?;
+        // 同一个 id 删掉后可能给了别的记录，从创建到删除算一段，每段是一条记录
+        let mut changes = Vec::new();
+        let mut current: HashMap<u32, usize> = HashMap::new();
+        for tx in &transactions {
+            for change in &tx.changes {
+                let lifetime = match current.get(&change.id) {
+                    Some(&lifetime) if change.before.is_some() => lifetime,
+                    _ => {
+                        current.insert(change.id, changes.len());
+                        changes.len()
+                    }
+                };
+                if change.after.is_none() {
+                    current.remove(&change.id);
+                }
+                changes.push((tx, change, lifetime));
+            }
+        }
+        // 再找出曾经叫这个名字的记录，改名前后的历史都要包括
+        let matching: BTreeSet<usize> = changes
+            .iter()
+            .filter(|(_, change, _)| change.concerns(name))
+            .map(|(_, _, lifetime)| *lifetime)
+            .collect();
+        let mut entries = Vec::new();
+        for (tx, change, lifetime) in changes {
+            if matching.contains(&lifetime) {
+                let name = change
+                    .after
+                    .as_ref()
+                    .or(change.before.as_ref())
+                    .map_or(String::new(), |r| r.name.clone());
+                entries.extend(change.events().into_iter().map(|event| HistoryEntry {
+                    timestamp: tx.timestamp,
+                    command: tx.command.clone(),
+                    record: change.id,
+                    name: name.clone(),
+                    event,
+                }));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// the latest transaction that is neither an undo nor undone yet
+    pub fn last_undoable(&self) -> Res<Option<Transaction>> {
+        let transactions = self.transactions()?;
+        let undone: BTreeSet<u32> = transactions.iter().filter_map(|t| t.undoes).collect();
+        Ok(transactions
+            .into_iter()
+            .rev()
+            .find(|t| t.undoes.is_none() && !undone.contains(&t.id)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(id: u32, name: &str) -> RecordData {
+        RecordData {
+            id,
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = HashMap::from([
+            (0, record(0, "a")),
+            (1, record(1, "b")),
+            (2, record(2, "c")),
+        ]);
+        let after = HashMap::from([
+            (0, record(0, "a")),
+            (2, record(2, "cc")),
+            (3, record(3, "d")),
+        ]);
+        let changes = diff(&before, &after);
+        assert_eq!(
+            vec![1, 2, 3],
+            changes.iter().map(|c| c.id).collect::<Vec<_>>()
+        );
+        assert!(changes[0].after.is_none());
+        assert_eq!("cc", changes[1].after.as_ref().unwrap().name);
+        assert!(changes[2].before.is_none());
+    }
+
+    #[test]
+    fn test_events() {
+        let mut before = record(3, "ripgrep");
+        before.version = Some(FlexibleVersion::parse("13.0.0"));
+        before.tags = vec!["cli".into(), "old".into()];
+        let mut after = before.clone();
+        after.version = Some(FlexibleVersion::parse("14.1.0"));
+        after.tags = vec!["cli".into(), "dev".into()];
+        after.location = Some(PathBuf::from("/usr/bin/rg"));
+        after.pin = Some(VersionRange::exact(FlexibleVersion::parse("14.1.0")));
+        let change = RecordChange {
+            id: 3,
+            before: Some(before),
+            after: Some(after),
+        };
+
+        let events: Vec<String> = change.events().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            vec![
+                "version 13.0.0 → 14.1.0",
+                "pin none → =14.1.0",
+                "location none → /usr/bin/rg",
+                "tags +dev -old"
+            ],
+            events
+        );
+        assert!(change.concerns("ripgrep"));
+        assert!(!change.concerns("bat"));
+    }
+
+    #[test]
+    fn test_last_undoable() -> Res<()> {
+        let dir = std::env::temp_dir().join(format!("fmn-journal-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let journal = Journal::new(&dir);
+        let tx = |undoes| Transaction {
+            id: 0,
+            timestamp: Utc::now(),
+            command: "record a".into(),
+            operations: vec![],
+            changes: vec![],
+            undoes,
+        };
+
+        assert!(journal.last_undoable()?.is_none());
+        journal.append(tx(None))?;
+        journal.append(tx(None))?;
+        assert_eq!(1, journal.last_undoable()?.unwrap().id);
+        journal.append(tx(Some(1)))?;
+        assert_eq!(0, journal.last_undoable()?.unwrap().id);
+        journal.append(tx(Some(0)))?;
+        assert!(journal.last_undoable()?.is_none());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_stops_at_removal() -> Res<()> {
+        let dir = std::env::temp_dir().join(format!("fmn-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let journal = Journal::new(&dir);
+        let tx =
+            |command: &str, before: Option<RecordData>, after: Option<RecordData>| Transaction {
+                id: 0,
+                timestamp: Utc::now(),
+                command: command.into(),
+                operations: vec![],
+                changes: vec![RecordChange {
+                    id: 1,
+                    before,
+                    after,
+                }],
+                undoes: None,
+            };
+        journal.append(tx("record a", None, Some(record(1, "a"))))?;
+        journal.append(tx("remove 1", Some(record(1, "a")), None))?;
+        // 旧版本会把删掉的 id 再分出去
+        journal.append(tx("record b", None, Some(record(1, "b"))))?;
+        journal.append(tx("rename 1", Some(record(1, "b")), Some(record(1, "bb"))))?;
+
+        let commands = |name| -> Res<Vec<String>> {
+            Ok(journal
+                .history(name)?
+                .into_iter()
+                .map(|e| e.command)
+                .collect())
+        };
+        assert_eq!(vec!["record a", "remove 1"], commands("a")?);
+        assert_eq!(vec!["record b", "rename 1"], commands("bb")?);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+use color_eyre::{
+    Result as Res,
+    eyre::{Context, ensure},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    data::{FlexibleVersion, RecordData},
+    source::{self, Source},
+};
+
+/// the version of the lockfile format
+const FORMAT: u32 = 1;
+
+const HEADER: &str = "# written by `fmn lock`, install it with `fmn apply --locked`\n\n";
+
+/// one package exactly as it was installed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Locked {
+    pub name: String,
+    /// where it comes from, see [`Source`]
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// the commit a git source was built from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// sha256 of the file downloaded for a url source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// sha256 of the installed file, for url and git sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+impl Locked {
+    /// lock a record at `version`, the commit for git sources
+    pub fn new(record: &RecordData, version: Option<&FlexibleVersion>) -> Option<Self> {
+        let source = Source::of(record)?.unversioned();
+        let version = version.map(|v| v.to_string());
+        let (version, commit) = match source {
+            Source::Git { .. } => (None, version),
+            _ => (version, None),
+        };
+        Some(Self {
+            name: record.name.clone(),
+            source: source.to_string(),
+            version,
+            commit,
+            sha256: record.sha256.clone(),
+            checksum: record.checksum.clone(),
+        })
+    }
+
+    /// a record of the package at its locked version
+    pub fn record(&self) -> Res<RecordData> {
+        let mut record = self.source.parse::<Source>()?.record();
+        record.name = self.name.clone();
+        record.version = self
+            .version
+            .as_ref()
+            .or(self.commit.as_ref())
+            .map(|v| FlexibleVersion::parse(v));
+        record.sha256 = self.sha256.clone();
+        record.checksum = self.checksum.clone();
+        match record.source.as_deref() {
+            Some(source::URL) => ensure!(
+                self.sha256.is_some(),
+                "`{}` has no sha256 in the lockfile, it cannot be installed exactly",
+                self.name
+            ),
+            Some(source::GIT) => ensure!(
+                self.commit.is_some(),
+                "`{}` has no commit in the lockfile, it cannot be installed exactly",
+                self.name
+            ),
+            _ => ensure!(
+                self.version.is_some(),
+                "`{}` has no version in the lockfile, it cannot be installed exactly",
+                self.name
+            ),
+        }
+        Ok(record)
+    }
+}
+
+/// exact versions of every recorded package, e.g. `fmn.lock`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lockfile {
+    version: u32,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<Locked>,
+}
+
+impl Lockfile {
+    /// sorted by source, so the file only changes where versions do
+    pub fn new(mut packages: Vec<Locked>) -> Self {
+        packages.sort_by(|a, b| (&a.source, &a.name).cmp(&(&b.source, &b.name)));
+        Self {
+            version: FORMAT,
+            packages,
+        }
+    }
+
+    pub fn load(path: &Path) -> Res<Self> {
+        let lockfile: Self = toml::from_str(
+            &read_to_string(path)
+                .with_context(|| format!("cannot read the lockfile `{}`", path.display()))?,
+        )
+        .with_context(|| format!("invalid lockfile `{}`", path.display()))?;
+        ensure!(
+            lockfile.version == FORMAT,
+            "`{}` is lockfile version {}, this fmn reads version {}",
+            path.display(),
+            lockfile.version,
+            FORMAT
+        );
+        Ok(lockfile)
+    }
+
+    pub fn save(&self, path: &Path) -> Res<()> {
+        write(path, format!("{}{}", HEADER, toml::to_string(self)?))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lock_and_record() -> Res<()> {
+        let mut cargo = "cargo:ripgrep@14".parse::<Source>()?.record();
+        cargo.version = Some(FlexibleVersion::parse("14.1.0"));
+        let mut git = "git+https://host/me/tool#v1".parse::<Source>()?.record();
+        git.version = Some(FlexibleVersion::parse("0123abcd"));
+        git.checksum = Some("ff".into());
+        let mut url = "https://host/jq".parse::<Source>()?.record();
+        url.sha256 = Some("ee".into());
+
+        let lockfile = Lockfile::new(
+            [&url, &git, &cargo]
+                .iter()
+                .filter_map(|r| Locked::new(r, r.version.as_ref()))
+                .collect(),
+        );
+        let sources: Vec<&str> = lockfile
+            .packages
+            .iter()
+            .map(|l| l.source.as_str())
+            .collect();
+        assert_eq!(
+            vec![
+                "cargo:ripgrep",
+                "git+https://host/me/tool#v1",
+                "https://host/jq"
+            ],
+            sources
+        );
+        assert_eq!(Some("0123abcd".to_string()), lockfile.packages[1].commit);
+        assert_eq!(None, lockfile.packages[1].version);
+
+        let dir = std::env::temp_dir().join(format!("fmn-lock-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        lockfile.save(&dir.join("fmn.lock"))?;
+        let text = read_to_string(dir.join("fmn.lock"))?;
+        assert!(text.starts_with(HEADER), "{}", text);
+        assert!(text.contains("[[package]]\nname = \"ripgrep\"\nsource = \"cargo:ripgrep\"\nversion = \"14.1.0\"\n"), "{}", text);
+        assert_eq!(lockfile, Lockfile::load(&dir.join("fmn.lock"))?);
+
+        for (locked, record) in lockfile.packages.iter().zip([&cargo, &git, &url]) {
+            let locked = locked.record()?;
+            assert_eq!(
+                (
+                    &record.name,
+                    &record.version,
+                    &record.origin,
+                    &record.sha256
+                ),
+                (
+                    &locked.name,
+                    &locked.version,
+                    &locked.origin,
+                    &locked.sha256
+                )
+            );
+        }
+        let mut unversioned = lockfile.packages[0].clone();
+        unversioned.version = None;
+        assert!(unversioned.record().is_err());
+
+        std::fs::write(dir.join("future.lock"), "version = 2\n")?;
+        assert!(Lockfile::load(&dir.join("future.lock")).is_err());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
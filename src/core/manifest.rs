@@ -0,0 +1,350 @@
+use std::{collections::BTreeMap, fs::read_to_string, path::Path, str::FromStr};
+
+use color_eyre::{
+    Report, Result as Res,
+    eyre::{Context, ensure},
+};
+use serde::Deserialize;
+
+use crate::core::{
+    data::{self, RecordData},
+    host,
+    source::{self, Source},
+    version::VersionRange,
+};
+
+/// what a manifest asks for one package
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    /// the versions allowed, kept as the pin of the record
+    pub version: Option<VersionRange>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    /// expected sha256 of the file downloaded for a url source
+    pub sha256: Option<String>,
+    /// hostnames it is for, `*` matches any characters, every host if empty
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// operating systems it is for, e.g. `linux` or `macos`, every one if empty
+    #[serde(default)]
+    pub os: Vec<String>,
+}
+
+impl Spec {
+    /// whether the package is wanted on the host `hostname` running `os`
+    pub fn applies(&self, hostname: &str, os: &str) -> bool {
+        (self.hosts.is_empty() || self.hosts.iter().any(|h| host::matches(h, hostname)))
+            && (self.os.is_empty() || self.os.iter().any(|o| o == os))
+    }
+
+    /// a new record of `source` as the manifest wants it
+    pub fn record(&self, source: &Source) -> RecordData {
+        let mut record = source.record();
+        record.version = self.version.as_ref().and_then(|v| v.as_exact()).cloned();
+        record.pin = self.version.clone();
+        record.tags = self.tags.clone();
+        record.description = self.description.clone();
+        record.sha256 = self.sha256.clone();
+        record
+    }
+}
+
+/// a package is either only a version range, or a table
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Version(VersionRange),
+    Spec(Spec),
+}
+
+/// a declarative list of packages, meant to be checked in, e.g. `fmn.toml`
+///
+/// # Example
+/// ```toml
+/// [cargo]
+/// ripgrep = "^14"
+/// bat = { version = "~0.24", tags = ["dev"] }
+///
+/// [flatpak]
+/// "flathub/org.gimp.GIMP" = { os = ["linux"], hosts = ["laptop", "desk-*"] }
+///
+/// [git]
+/// "https://github.com/me/tool#v1.0" = {}
+///
+/// [url]
+/// "https://example.com/jq-linux-amd64" = { sha256 = "..." }
+/// ```
+///
+/// a key is the source without its manager, see [`Source`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    pub packages: Vec<(Source, Spec)>,
+}
+
+impl FromStr for Manifest {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Res<Self> {
+        let tables: BTreeMap<String, BTreeMap<String, Entry>> = toml::from_str(s)?;
+        let mut packages = Vec::new();
+        for (manager, entries) in tables {
+            for (key, entry) in entries {
+                let source: Source = match manager.as_str() {
+                    source::GIT => format!("git+{}", key),
+                    source::URL => key.clone(),
+                    _ => format!("{}:{}", manager, key),
+                }
+                .parse()?;
+                ensure!(
+                    !matches!(
+                        source,
+                        Source::Package {
+                            version: Some(_),
+                            ..
+                        }
+                    ),
+                    "`{}` has a version in its name, write it as `{} = \"<version>\"` under [{}]",
+                    key,
+                    source.name(),
+                    manager
+                );
+                let mut spec = match entry {
+                    Entry::Version(version) => Spec {
+                        version: Some(version),
+                        ..Default::default()
+                    },
+                    Entry::Spec(spec) => spec,
+                };
+                // `*` 不算 pin
+                spec.version = spec.version.filter(|v| v.to_string() != "*");
+                for tag in &spec.tags {
+                    data::validate_tag(tag)?;
+                }
+                packages.push((source, spec));
+            }
+        }
+        Ok(Self { packages })
+    }
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Res<Self> {
+        read_to_string(path)
+            .with_context(|| format!("cannot read the manifest `{}`", path.display()))?
+            .parse()
+            .with_context(|| format!("invalid manifest `{}`", path.display()))
+    }
+
+    /// the packages wanted on this host
+    pub fn here(&self) -> Vec<&(Source, Spec)> {
+        let (hostname, os) = (host::hostname(), host::os());
+        self.packages
+            .iter()
+            .filter(|(_, spec)| spec.applies(&hostname, os))
+            .collect()
+    }
+
+    /// a manifest of the records with a source, pins become version ranges
+    pub fn from_records(records: &[&RecordData]) -> Self {
+        let packages = records
+            .iter()
+            .filter_map(|record| {
+                let source = Source::of(record)?.unversioned();
+                let spec = Spec {
+                    version: record.pin.clone(),
+                    tags: record.tags.clone(),
+                    description: record.description.clone(),
+                    sha256: record.sha256.clone(),
+                    ..Default::default()
+                };
+                Some((source, spec))
+            })
+            .collect();
+        Self { packages }
+    }
+}
+
+/// a bare key if it can be one, otherwise a quoted one
+fn key(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        s.to_string()
+    } else {
+        toml::Value::from(s).to_string()
+    }
+}
+
+impl std::fmt::Display for Manifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut tables: BTreeMap<&str, BTreeMap<String, String>> = BTreeMap::new();
+        for (source, spec) in &self.packages {
+            let name = match source {
+                Source::Package { remote, name, .. } => match remote {
+                    Some(remote) => format!("{}/{}", remote, name),
+                    None => name.clone(),
+                },
+                _ => source.origin().unwrap_or_default(),
+            };
+            let version = spec
+                .version
+                .as_ref()
+                .map_or("*".to_string(), |v| v.to_string());
+            let only_version = Spec {
+                version: spec.version.clone(),
+                ..Default::default()
+            };
+            let value = if *spec == only_version {
+                toml::Value::from(version).to_string()
+            } else {
+                let list = |items: &[String]| toml::Value::from(items.to_vec()).to_string();
+                let mut fields = Vec::new();
+                if let Some(version) = &spec.version {
+                    fields.push(format!(
+                        "version = {}",
+                        toml::Value::from(version.to_string())
+                    ));
+                }
+                if !spec.tags.is_empty() {
+                    fields.push(format!("tags = {}", list(&spec.tags)));
+                }
+                if let Some(description) = &spec.description {
+                    fields.push(format!(
+                        "description = {}",
+                        toml::Value::from(description.as_str())
+                    ));
+                }
+                if let Some(sha256) = &spec.sha256 {
+                    fields.push(format!("sha256 = {}", toml::Value::from(sha256.as_str())));
+                }
+                if !spec.hosts.is_empty() {
+                    fields.push(format!("hosts = {}", list(&spec.hosts)));
+                }
+                if !spec.os.is_empty() {
+                    fields.push(format!("os = {}", list(&spec.os)));
+                }
+                format!("{{ {} }}", fields.join(", "))
+            };
+            tables
+                .entry(source.manager())
+                .or_default()
+                .insert(key(&name), value);
+        }
+        for (i, (manager, entries)) in tables.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", key(manager))?;
+            for (name, value) in entries {
+                writeln!(f, "{} = {}", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[cargo]
+ripgrep = "^14"
+bat = { version = "~0.24", tags = ["dev"], description = "cat with wings" }
+tokei = "*"
+
+[flatpak]
+"flathub/org.gimp.GIMP" = { os = ["linux"], hosts = ["laptop", "desk-*"] }
+
+[git]
+"https://github.com/me/tool#v1.0" = {}
+
+[url]
+"https://example.com/jq-linux-amd64" = { sha256 = "abc" }
+"#;
+
+    #[test]
+    fn test_parse() -> Res<()> {
+        let manifest: Manifest = MANIFEST.parse()?;
+        let sources: Vec<String> = manifest
+            .packages
+            .iter()
+            .map(|(s, _)| s.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "cargo:bat",
+                "cargo:ripgrep",
+                "cargo:tokei",
+                "flatpak:flathub/org.gimp.GIMP",
+                "git+https://github.com/me/tool#v1.0",
+                "https://example.com/jq-linux-amd64",
+            ],
+            sources
+        );
+        let (_, bat) = &manifest.packages[0];
+        assert_eq!("~0.24", bat.version.as_ref().unwrap().to_string());
+        assert_eq!(vec!["dev"], bat.tags);
+
+        let (gimp_source, gimp) = &manifest.packages[3];
+        assert!(gimp.applies("desk-2", "linux"));
+        assert!(!gimp.applies("desk-2", "macos"));
+        assert!(!gimp.applies("server", "linux"));
+        assert!(manifest.packages[0].1.applies("anything", "windows"));
+
+        let record = gimp.record(gimp_source);
+        assert_eq!(Some("flathub".to_string()), record.origin);
+        assert_eq!(None, record.pin);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        for manifest in [
+            "[cargo]\nripgrep = \"^x\"",
+            "[cargo]\n\"ripgrep@14\" = \"*\"",
+            "[cargo]\nripgrep = { verison = \"14\" }",
+            "[cargo]\nripgrep = { tags = [\"two words\"] }",
+            "[url]\n\"not a url\" = {}",
+            "cargo = 1",
+        ] {
+            assert!(manifest.parse::<Manifest>().is_err(), "{}", manifest);
+        }
+    }
+
+    #[test]
+    fn test_record_and_render() -> Res<()> {
+        let (source, spec): (Source, Spec) = (
+            "cargo:ripgrep".parse()?,
+            Spec {
+                version: Some("14.1.0".parse()?),
+                ..Default::default()
+            },
+        );
+        let mut record = spec.record(&source);
+        assert_eq!(
+            Some("14.1.0".to_string()),
+            record.version.as_ref().map(|v| v.to_string())
+        );
+        assert_eq!(
+            Some("=14.1.0".to_string()),
+            record.pin.as_ref().map(|v| v.to_string())
+        );
+
+        record.tags = vec!["dev".into()];
+        let other = "apt:jq".parse::<Source>()?.record();
+        let rendered = Manifest::from_records(&[&record, &other]).to_string();
+        assert_eq!(
+            "[apt]\njq = \"*\"\n\n[cargo]\nripgrep = { version = \"=14.1.0\", tags = [\"dev\"] }\n",
+            rendered
+        );
+
+        // 生成的清单再读回来应该一样
+        let manifest: Manifest = MANIFEST.parse()?;
+        assert_eq!(manifest, manifest.to_string().parse()?);
+        Ok(())
+    }
+}
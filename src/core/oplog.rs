@@ -0,0 +1,295 @@
+use std::{
+    fs::{OpenOptions, create_dir_all, read_to_string, write},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{Result as Res, eyre::eyre};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use crate::core::scheduler::JobResult;
+
+const LOG_FILE_NAME: &str = "log.jsonl";
+/// how much of the output is kept in the log itself, the full output lives next to it
+const OUTPUT_TAIL: usize = 2048;
+
+/// one command fmn ran
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    pub id: u32,
+    pub timestamp: DateTime<Utc>,
+    pub manager: String,
+    pub argv: Vec<String>,
+    /// `None` if the process was killed, or could not be spawned at all
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub records: Vec<u32>,
+    /// why the command failed besides its exit code, e.g. a timeout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// tail of stdout
+    pub stdout: String,
+    /// tail of stderr
+    pub stderr: String,
+}
+
+impl Operation {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0) && self.error.is_none()
+    }
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match (self.exit_code, &self.error) {
+            (_, Some(_)) => "error".red(),
+            (Some(0), None) => "ok".green(),
+            (Some(code), None) => format!("exit {}", code).red(),
+            (None, None) => "killed".red(),
+        };
+        write!(
+            f,
+            "{:>4}  {}  {:<8} {:<6} {:>7.1}s  {}",
+            self.id,
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.manager,
+            status,
+            self.duration_ms as f64 / 1000.0,
+            self.argv.join(" ")
+        )?;
+        if !self.records.is_empty() {
+            let ids: Vec<String> = self.records.iter().map(|id| id.to_string()).collect();
+            write!(f, "  [records: {}]", ids.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// filters for browsing the log, unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub manager: Option<String>,
+    pub record: Option<u32>,
+    pub failed: bool,
+    /// only the last n entries
+    pub limit: Option<usize>,
+}
+
+impl Filter {
+    fn matches(&self, op: &Operation) -> bool {
+        self.manager.as_ref().is_none_or(|m| *m == op.manager)
+            && self.record.is_none_or(|id| op.records.contains(&id))
+            && (!self.failed || !op.success())
+    }
+}
+
+/// append-only log of every command fmn ran
+///
+/// entries are stored as json lines, the full output of each entry in `<id>.stdout` and `<id>.stderr`
+#[derive(Debug)]
+pub struct OpLog {
+    dir: PathBuf,
+}
+
+impl OpLog {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.dir.join(LOG_FILE_NAME)
+    }
+
+    fn output_path(&self, id: u32, stream: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, stream))
+    }
+
+    pub fn entries(&self) -> Res<Vec<Operation>> {
+        let path = self.log_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        read_to_string(path)?
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| Ok(serde_json::from_str(l)?))
+            .collect()
+    }
+
+    pub fn filter(&self, filter: &Filter) -> Res<Vec<Operation>> {
+        let mut entries: Vec<Operation> = self
+            .entries()?
+            .into_iter()
+            .filter(|op| filter.matches(op))
+            .collect();
+        if let Some(limit) = filter.limit {
+            entries.drain(..entries.len().saturating_sub(limit));
+        }
+        Ok(entries)
+    }
+
+    pub fn get(&self, id: u32) -> Res<Operation> {
+        self.entries()?
+            .into_iter()
+            .find(|op| op.id == id)
+            .ok_or_else(|| eyre!("no operation with id {}", id))
+    }
+
+    /// full stdout and stderr of an entry
+    pub fn output(&self, id: u32) -> Res<(String, String)> {
+        let read = |stream| {
+            let path = self.output_path(id, stream);
+            if path.exists() {
+                read_to_string(path)
+            } else {
+                Ok(String::new())
+            }
+        };
+        Ok((read("stdout")?, read("stderr")?))
+    }
+
+    /// point the entries of record `from` at `to`, when a sync gave `from` to another record
+    pub fn renumber(&self, from: u32, to: u32) -> Res<()> {
+        let path = self.log_path();
+        if !path.exists() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for mut op in self.entries()? {
+            for id in op.records.iter_mut().filter(|id| **id == from) {
+                *id = to;
+            }
+            lines += &(serde_json::to_string(&op)? + "\n");
+        }
+        write(path, lines)?;
+        Ok(())
+    }
+
+    /// append the result of a job, returns the id of the new entry
+    pub fn append(&self, result: &JobResult) -> Res<u32> {
+        create_dir_all(&self.dir)?;
+        let id = self.entries()?.last().map_or(0, |op| op.id + 1);
+        let (exit_code, error) = match &result.status {
+            Ok(code) => (*code, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let op = Operation {
+            id,
+            timestamp: result.started,
+            manager: result.job.manager.clone(),
+            argv: result.job.argv.clone(),
+            exit_code,
+            duration_ms: result.duration.as_millis() as u64,
+            records: result.job.records.clone(),
+            error,
+            stdout: tail(&result.output.stdout, OUTPUT_TAIL).to_string(),
+            stderr: tail(&result.output.stderr, OUTPUT_TAIL).to_string(),
+        };
+
+        write(self.output_path(id, "stdout"), &result.output.stdout)?;
+        write(self.output_path(id, "stderr"), &result.output.stderr)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())?;
+        writeln!(file, "{}", serde_json::to_string(&op)?)?;
+        Ok(id)
+    }
+}
+
+/// the last `max` bytes of `s`, cut at a char boundary
+fn tail(s: &str, max: usize) -> &str {
+    let mut start = s.len().saturating_sub(max);
+    while !s.is_char_boundary(start) {
+        start += 1;
+    }
+    &s[start..]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{exec::Output, scheduler::Job};
+    use color_eyre::eyre::eyre;
+    use std::time::Duration;
+
+    fn result(manager: &str, status: Res<Option<i32>>, records: Vec<u32>) -> JobResult {
+        let mut job = Job::new(
+            manager,
+            vec![manager.into(), "install".into(), "pkg".into()],
+        );
+        job.records = records;
+        JobResult {
+            job,
+            status,
+            output: Output {
+                stdout: "x".repeat(OUTPUT_TAIL + 10),
+                stderr: "warning: 你好\n".into(),
+            },
+            started: Utc::now(),
+            duration: Duration::from_millis(1500),
+        }
+    }
+
+    #[test]
+    fn test_append_and_filter() -> Res<()> {
+        let dir = std::env::temp_dir().join(format!("fmn-oplog-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let log = OpLog::new(&dir);
+
+        assert_eq!(0, log.append(&result("apt", Ok(Some(0)), vec![3]))?);
+        assert_eq!(1, log.append(&result("cargo", Ok(Some(101)), vec![]))?);
+        assert_eq!(
+            2,
+            log.append(&result("apt", Err(eyre!("timed out")), vec![4]))?
+        );
+
+        let op = log.get(0)?;
+        assert_eq!(vec!["apt", "install", "pkg"], op.argv);
+        assert_eq!(OUTPUT_TAIL, op.stdout.len());
+        assert_eq!(1500, op.duration_ms);
+        let (stdout, stderr) = log.output(0)?;
+        assert_eq!(OUTPUT_TAIL + 10, stdout.len());
+        assert_eq!("warning: 你好\n", stderr);
+
+        let failed = log.filter(&Filter {
+            failed: true,
+            ..Default::default()
+        })?;
+        assert_eq!(
+            vec![1, 2],
+            failed.iter().map(|op| op.id).collect::<Vec<_>>()
+        );
+        assert_eq!(Some("timed out".into()), failed[1].error);
+
+        let apt = log.filter(&Filter {
+            manager: Some("apt".into()),
+            limit: Some(1),
+            ..Default::default()
+        })?;
+        assert_eq!(vec![2], apt.iter().map(|op| op.id).collect::<Vec<_>>());
+
+        let rec = log.filter(&Filter {
+            record: Some(3),
+            ..Default::default()
+        })?;
+        assert_eq!(vec![0], rec.iter().map(|op| op.id).collect::<Vec<_>>());
+        log.renumber(3, 7)?;
+        assert_eq!(vec![7], log.get(0)?.records);
+        assert_eq!(vec![4], log.get(2)?.records);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_tail_char_boundary() {
+        assert_eq!("好", tail("你好", 4));
+        assert_eq!("abc", tail("abc", 10));
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use colored::Colorize;
+
+use crate::core::version::{Change, FlexibleVersion, VersionRange};
+
+/// a recorded package with a newer version available from its manager
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outdated {
+    pub id: u32,
+    pub name: String,
+    pub source: String,
+    pub current: Option<FlexibleVersion>,
+    pub available: FlexibleVersion,
+    pub pin: Option<VersionRange>,
+}
+
+impl Outdated {
+    /// `None` if the current version was never recorded
+    pub fn change(&self) -> Option<Change> {
+        self.current
+            .as_ref()
+            .map(|current| Change::between(current, &self.available))
+    }
+
+    /// whether the available version is within the pin, if any
+    pub fn allowed(&self) -> bool {
+        self.pin
+            .as_ref()
+            .is_none_or(|pin| pin.matches(&self.available))
+    }
+}
+
+/// parse the output of an `outdated` command, one package per line
+///
+/// the name is the first column, the available version follows the last `<`, `->` or `→`,
+/// or is the last column, e.g. `ripgrep (13.0.0) < 14.1.0` from `brew outdated --verbose`
+pub fn parse(stdout: &str) -> HashMap<String, FlexibleVersion> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            let name = columns.first()?;
+            let available = match columns.iter().rposition(|c| ["<", "->", "→"].contains(c)) {
+                Some(arrow) => columns.get(arrow + 1)?,
+                None if columns.len() > 1 => columns.last()?,
+                None => return None,
+            };
+            Some((name.to_string(), FlexibleVersion::parse(available)))
+        })
+        .collect()
+}
+
+/// the outdated packages as aligned rows: name, source, current → available, change, pin
+pub fn table(outdated: &[Outdated]) -> Vec<String> {
+    let cells: Vec<[String; 4]> = outdated
+        .iter()
+        .map(|o| {
+            [
+                o.name.clone(),
+                o.source.clone(),
+                o.current
+                    .as_ref()
+                    .map_or("?".to_string(), |v| v.to_string()),
+                o.available.to_string(),
+            ]
+        })
+        .collect();
+    let width = |i: usize| {
+        cells
+            .iter()
+            .map(|c| c[i].chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let widths = [width(0), width(1), width(2), width(3)];
+
+    outdated
+        .iter()
+        .zip(&cells)
+        .map(|(o, [name, source, current, available])| {
+            // 先补齐宽度再上色，颜色的转义序列不算宽度
+            let change = match o.change() {
+                Some(Change::Major) => "major".red(),
+                Some(Change::Minor) => "minor".yellow(),
+                Some(Change::Patch) => "patch".green(),
+                Some(Change::Other) => "other".normal(),
+                None => "?".normal(),
+            };
+            let mut row = format!(
+                "{:<w0$}  {:<w1$}  {:>w2$} → {:<w3$}  {:<5}",
+                name,
+                source,
+                current,
+                available,
+                change,
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+            );
+            match &o.pin {
+                Some(pin) if o.allowed() => row += &format!("  pinned {}", pin),
+                Some(pin) => row += &format!("  {}", format!("held back by {}", pin).dimmed()),
+                None => {}
+            }
+            row.trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let available = parse(
+            "ripgrep (13.0.0) < 14.1.0\n\
+             fd 9.0.0 -> 10.2.0\n\
+             bat 0.25.0\n\
+             lonely\n\
+             \n\
+             jq (1.6) < 1.7.1 [pinned at 1.6]\n",
+        );
+        assert_eq!(4, available.len());
+        assert_eq!(FlexibleVersion::parse("14.1.0"), available["ripgrep"]);
+        assert_eq!(FlexibleVersion::parse("10.2.0"), available["fd"]);
+        assert_eq!(FlexibleVersion::parse("0.25.0"), available["bat"]);
+        assert_eq!(FlexibleVersion::parse("1.7.1"), available["jq"]);
+    }
+
+    #[test]
+    fn test_table() {
+        colored::control::set_override(false);
+        let outdated = |name: &str, current: &str, available: &str, pin: Option<&str>| Outdated {
+            id: 0,
+            name: name.into(),
+            source: "brew".into(),
+            current: Some(FlexibleVersion::parse(current)),
+            available: FlexibleVersion::parse(available),
+            pin: pin.map(|p| p.parse().unwrap()),
+        };
+        let rows = table(&[
+            outdated("ripgrep", "13.0.0", "14.1.0", None),
+            outdated("jq", "1.7", "1.7.1", Some("~1.7")),
+            outdated("node", "20.1.0", "22.0.0", Some("^20")),
+        ]);
+        assert_eq!(
+            vec![
+                "ripgrep  brew  13.0.0 → 14.1.0  major",
+                "jq       brew     1.7 → 1.7.1   patch  pinned ~1.7",
+                "node     brew  20.1.0 → 22.0.0  major  held back by ^20",
+            ],
+            rows
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use color_eyre::{
+    Result as Res,
+    eyre::{bail, eyre},
+};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::core::version::FlexibleVersion;
+
+/// one package found in the output of a manager command
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Package {
+    pub name: String,
+    pub version: Option<FlexibleVersion>,
+    /// the newest version available, reported by e.g. `outdated`
+    pub latest: Option<FlexibleVersion>,
+    /// where the manager got it from, e.g. a repository, remote or git url
+    pub source: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Package {
+    /// build a package from the extracted fields, `None` without a name
+    fn from_fields(field: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let present = |name: &str| {
+            field(name)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        Some(Self {
+            name: present("name")?,
+            version: present("version").map(|v| FlexibleVersion::parse(&v)),
+            latest: present("latest").map(|v| FlexibleVersion::parse(&v)),
+            source: present("source"),
+            description: present("description"),
+        })
+    }
+}
+
+impl std::fmt::Display for Package {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, " {}", version)?;
+        }
+        if let Some(latest) = &self.latest {
+            write!(f, " → {}", latest)?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, " ({})", source)?;
+        }
+        if let Some(description) = &self.description {
+            write!(f, "  {}", description)?;
+        }
+        Ok(())
+    }
+}
+
+/// the output of a command, parsed into packages if there is a parser for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listing {
+    Packages(Vec<Package>),
+    Raw(String),
+}
+
+/// a regex compiled when the config is loaded, so a typo is reported right away
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// columns split on whitespace, counted from 1 like awk, negative ones from the end
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Columns {
+    #[serde(default = "Columns::first")]
+    pub name: isize,
+    pub version: Option<isize>,
+    pub latest: Option<isize>,
+    pub source: Option<isize>,
+    pub description: Option<isize>,
+}
+
+impl Columns {
+    fn first() -> isize {
+        1
+    }
+
+    fn index(&self, field: &str) -> Option<isize> {
+        match field {
+            "name" => Some(self.name),
+            "version" => self.version,
+            "latest" => self.latest,
+            "source" => self.source,
+            "description" => self.description,
+            _ => None,
+        }
+    }
+
+    fn get(&self, cells: &[&str], field: &str) -> Option<String> {
+        let index = match self.index(field)? {
+            i if i > 0 => i as usize - 1,
+            i if i < 0 => cells.len().checked_sub(i.unsigned_abs())?,
+            _ => return None,
+        };
+        cells.get(index).map(|c| c.to_string())
+    }
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self {
+            name: 1,
+            version: Some(2),
+            latest: None,
+            source: None,
+            description: None,
+        }
+    }
+}
+
+/// JSON pointers (RFC 6901), e.g. `/dependencies`
+///
+/// `path` points to an array of packages, or to an object keyed by package name,
+/// the other pointers are relative to a package
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Json {
+    #[serde(default)]
+    pub path: String,
+    /// the key of the object if not given
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub latest: Option<String>,
+    pub source: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Json {
+    fn pointer(&self, field: &str) -> Option<&str> {
+        match field {
+            "name" => self.name.as_deref(),
+            "version" => self.version.as_deref(),
+            "latest" => self.latest.as_deref(),
+            "source" => self.source.as_deref(),
+            "description" => self.description.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn package(&self, key: Option<&str>, item: &Value) -> Option<Package> {
+        Package::from_fields(|field| {
+            let Some(pointer) = self.pointer(field) else {
+                return key.filter(|_| field == "name").map(String::from);
+            };
+            match item.pointer(pointer)? {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                Value::Bool(b) => Some(b.to_string()),
+                _ => None,
+            }
+        })
+    }
+
+    /// packages in one JSON document
+    fn parse(&self, document: &str) -> Res<Vec<Package>> {
+        let document: Value = serde_json::from_str(document)?;
+        let node = document
+            .pointer(&self.path)
+            .ok_or_else(|| eyre!("`{}` is not in the output", self.path))?;
+        Ok(match node {
+            Value::Array(items) => items
+                .iter()
+                .filter_map(|item| self.package(None, item))
+                .collect(),
+            Value::Object(items) => items
+                .iter()
+                .filter_map(|(key, item)| self.package(Some(key), item))
+                .collect(),
+            _ => bail!("`{}` is neither an array nor an object", self.path),
+        })
+    }
+
+    /// one JSON document per line, `path` points to the package within each of them
+    fn parse_lines<'a>(&self, lines: impl Iterator<Item = &'a str>) -> Res<Vec<Package>> {
+        let mut packages = Vec::new();
+        for line in lines.filter(|l| !l.trim().is_empty()) {
+            let document: Value = serde_json::from_str(line)?;
+            let item = document
+                .pointer(&self.path)
+                .ok_or_else(|| eyre!("`{}` is not in `{}`", self.path, line))?;
+            packages.extend(self.package(None, item));
+        }
+        Ok(packages)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// matched against every line, with the named groups
+    /// `name`, `version`, `latest`, `source` and `description`
+    Regex(Pattern),
+    Columns(Columns),
+    Json(Json),
+    Jsonl(Json),
+}
+
+/// how to read packages from the output of a manager command
+///
+/// # Example
+/// ```toml
+/// parse = { regex = '^(?P<name>\S+) \((?P<version>.+)\) < (?P<latest>\S+)' }
+/// parse = { skip = 1, columns = { name = 1, version = -1 } }
+/// parse = { json = { path = "/dependencies", version = "/version" } }
+/// parse = { jsonl = { name = "/name", version = "/version" } }
+/// ```
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct OutputParser {
+    /// header lines to ignore, e.g. `Listing...` printed by apt
+    #[serde(default)]
+    pub skip: usize,
+    #[serde(flatten)]
+    pub format: Format,
+}
+
+impl From<Format> for OutputParser {
+    fn from(format: Format) -> Self {
+        Self { skip: 0, format }
+    }
+}
+
+impl OutputParser {
+    /// lines that do not match are skipped
+    pub fn parse(&self, stdout: &str) -> Res<Vec<Package>> {
+        let lines = stdout.lines().skip(self.skip);
+        match &self.format {
+            Format::Regex(Pattern(regex)) => Ok(lines
+                .filter_map(|line| {
+                    let captures = regex.captures(line)?;
+                    Package::from_fields(|field| {
+                        captures.name(field).map(|m| m.as_str().to_string())
+                    })
+                })
+                .collect()),
+            Format::Columns(columns) => Ok(lines
+                .filter_map(|line| {
+                    let cells: Vec<&str> = line.split_whitespace().collect();
+                    Package::from_fields(|field| columns.get(&cells, field))
+                })
+                .collect()),
+            Format::Json(json) => json.parse(&lines.collect::<Vec<_>>().join("\n")),
+            Format::Jsonl(json) => json.parse_lines(lines),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parser(toml: &str) -> OutputParser {
+        #[derive(Deserialize)]
+        struct Spec {
+            parse: OutputParser,
+        }
+        toml::from_str::<Spec>(toml).unwrap().parse
+    }
+
+    fn summary(packages: &[Package]) -> Vec<String> {
+        packages.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_regex() -> Res<()> {
+        let parser =
+            parser(r#"parse = { regex = '^(?P<name>\S+) \((?P<version>.+)\) < (?P<latest>\S+)' }"#);
+        let packages =
+            parser.parse("ripgrep (13.0.0) < 14.1.0\nnot a match\njq (1.6) < 1.7.1\n")?;
+        assert_eq!(
+            vec!["ripgrep 13.0.0 → 14.1.0", "jq 1.6 → 1.7.1"],
+            summary(&packages)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_spec() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Spec {
+            parse: OutputParser,
+        }
+        for spec in [
+            "parse = { regex = '(' }",
+            "parse = { skip = 1 }",
+            "parse = { columns = { name = 1, colour = 2 } }",
+        ] {
+            assert!(toml::from_str::<Spec>(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_columns() -> Res<()> {
+        let parser = parser("parse = { skip = 1, columns = { name = 1, version = -1 } }");
+        let packages = parser.parse("Name Version\nbat 0.24.0 0.25.0\nfd 10.2.0\n\n")?;
+        assert_eq!(vec!["bat 0.25.0", "fd 10.2.0"], summary(&packages));
+        let packages = OutputParser::from(Format::Columns(Columns::default())).parse("lonely\n")?;
+        assert_eq!(vec!["lonely"], summary(&packages));
+        Ok(())
+    }
+
+    #[test]
+    fn test_json() -> Res<()> {
+        // npm ls --json
+        let npm = parser(r#"parse = { json = { path = "/dependencies", version = "/version" } }"#);
+        let packages = npm.parse(
+            r#"{"name": "global", "dependencies": {"typescript": {"version": "5.4.5"}, "pnpm": {"version": "9.1.0"}}}"#,
+        )?;
+        assert_eq!(vec!["pnpm 9.1.0", "typescript 5.4.5"], summary(&packages));
+
+        // pip list --format=json
+        let pip = parser(r#"parse = { json = { name = "/name", version = "/version" } }"#);
+        let packages =
+            pip.parse(r#"[{"name": "requests", "version": "2.32.3"}, {"version": "1"}]"#)?;
+        assert_eq!(vec!["requests 2.32.3"], summary(&packages));
+
+        assert!(npm.parse(r#"{"name": "global"}"#).is_err());
+        assert!(npm.parse("not json").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jsonl() -> Res<()> {
+        let parser = parser(
+            r#"parse = { skip = 1, jsonl = { path = "/package", name = "/id", version = "/version/installed", source = "/origin" } }"#,
+        );
+        let packages = parser.parse(
+            "warning: not json\n\
+             {\"package\": {\"id\": \"gimp\", \"version\": {\"installed\": \"2.10.38\"}, \"origin\": \"flathub\"}}\n\
+             \n\
+             {\"package\": {\"id\": \"vlc\"}}\n",
+        )?;
+        assert_eq!(vec!["gimp 2.10.38 (flathub)", "vlc"], summary(&packages));
+        assert!(parser.parse("header\n{\"other\": 1}\n").is_err());
+        Ok(())
+    }
+}
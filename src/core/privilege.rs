@@ -0,0 +1,220 @@
+use color_eyre::{
+    Result as Res,
+    eyre::{bail, ensure},
+};
+use serde::Deserialize;
+
+/// which user a package manager has to run as
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Privilege {
+    /// needs root, e.g. apt, dnf, pacman
+    Root,
+    /// must never run as root, e.g. cargo, pip --user, flatpak --user
+    User,
+    /// run as whoever runs fmn
+    #[default]
+    Auto,
+}
+
+/// command used to gain root
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String")]
+pub enum Escalation {
+    #[default]
+    Sudo,
+    Doas,
+}
+
+impl TryFrom<String> for Escalation {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "sudo" => Ok(Self::Sudo),
+            "doas" => Ok(Self::Doas),
+            // 两个都通过 polkit agent 要密码，后台跑的任务没法回答
+            "pkexec" | "run0" => Err(format!(
+                "`{}` is not supported, it asks a polkit agent for the password on every command, \
+                 which the jobs fmn runs cannot answer; use `sudo` or `doas`, or run fmn as root",
+                s
+            )),
+            _ => Err(format!(
+                "unknown escalation `{}`, expected `sudo` or `doas`",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Privilege {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Root => "root",
+            Self::User => "user",
+            Self::Auto => "auto",
+        };
+        // 用 pad 才能让 {:<4} 之类的对齐生效
+        f.pad(s)
+    }
+}
+
+impl Escalation {
+    const ALL: [Self; 2] = [Self::Sudo, Self::Doas];
+
+    pub fn program(&self) -> &'static str {
+        match self {
+            Self::Sudo => "sudo",
+            Self::Doas => "doas",
+        }
+    }
+
+    /// the escalation `argv` starts with, if any
+    pub fn of(argv: &[String]) -> Option<Self> {
+        let program = argv.first()?;
+        Self::ALL.into_iter().find(|e| e.program() == program)
+    }
+
+    /// ask for the password once up front, jobs run without a terminal and cannot ask themselves
+    ///
+    /// fails if it will not be remembered between commands
+    pub fn authenticate(&self) -> Res<()> {
+        let run = |args: &[&str]| -> Res<bool> {
+            Ok(std::process::Command::new(self.program())
+                .args(args)
+                .status()?
+                .success())
+        };
+        match self {
+            Self::Sudo => ensure!(run(&["-v"])?, "failed to gain root via sudo"),
+            Self::Doas => {
+                ensure!(run(&["true"])?, "failed to gain root via doas");
+                // 没有 persist 的话每条命令都要再输密码
+                ensure!(
+                    run(&["-n", "true"])?,
+                    "doas does not remember the password, add `persist` to your rule in /etc/doas.conf, or run fmn as root"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// whether fmn itself is running as root, e.g. under sudo
+pub fn is_root() -> bool {
+    #[cfg(unix)]
+    {
+        // SAFETY: geteuid has no preconditions and never fails
+        unsafe { libc::geteuid() == 0 }
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+impl Privilege {
+    /// apply the policy to `argv` of `manager`
+    ///
+    /// prepends the escalation command when root is needed,
+    /// refuses to run a user-only manager as root
+    pub fn wrap(
+        &self,
+        manager: &str,
+        argv: Vec<String>,
+        escalation: Escalation,
+        running_as_root: bool,
+    ) -> Res<Vec<String>> {
+        match self {
+            Self::User if running_as_root => bail!(
+                "`{}` must not run as root, rerun fmn as a normal user (without {})",
+                manager,
+                escalation.program()
+            ),
+            Self::Root if !running_as_root => {
+                if which::which(escalation.program()).is_err() {
+                    bail!(
+                        "`{}` needs root, but `{}` was not found",
+                        manager,
+                        escalation.program()
+                    );
+                }
+                Ok(std::iter::once(escalation.program().to_string())
+                    .chain(argv)
+                    .collect())
+            }
+            _ => Ok(argv),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn argv() -> Vec<String> {
+        vec!["apt".into(), "install".into(), "ripgrep".into()]
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            argv(),
+            Privilege::Auto
+                .wrap("apt", argv(), Escalation::Sudo, false)
+                .unwrap()
+        );
+        assert_eq!(
+            argv(),
+            Privilege::Root
+                .wrap("apt", argv(), Escalation::Sudo, true)
+                .unwrap()
+        );
+        assert_eq!(
+            argv(),
+            Privilege::User
+                .wrap("apt", argv(), Escalation::Sudo, false)
+                .unwrap()
+        );
+        assert!(
+            Privilege::User
+                .wrap("cargo", argv(), Escalation::Sudo, true)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_wrap_escalates() {
+        // 测试机上不一定装了 doas，两种结果都要接受
+        match Privilege::Root.wrap("apt", argv(), Escalation::Doas, false) {
+            Ok(wrapped) => assert_eq!(["doas", "apt", "install", "ripgrep"], wrapped[..]),
+            Err(e) => assert!(e.to_string().contains("`doas` was not found")),
+        }
+    }
+
+    #[test]
+    fn test_escalation_of() {
+        assert_eq!(None, Escalation::of(&argv()));
+        for escalation in Escalation::ALL {
+            let mut argv = argv();
+            argv.insert(0, escalation.program().into());
+            assert_eq!(Some(escalation), Escalation::of(&argv));
+        }
+    }
+
+    #[test]
+    fn test_toml_parse_privilege() {
+        #[derive(Deserialize)]
+        struct T {
+            privilege: Privilege,
+            escalate: Escalation,
+        }
+        let t: T = toml::from_str("privilege = \"root\"\nescalate = \"doas\"").unwrap();
+        assert_eq!(Privilege::Root, t.privilege);
+        assert_eq!(Escalation::Doas, t.escalate);
+        for polkit in ["pkexec", "run0"] {
+            let toml = format!("privilege = \"root\"\nescalate = \"{}\"", polkit);
+            let err = toml::from_str::<T>(&toml).err().unwrap();
+            assert!(err.to_string().contains("polkit"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use color_eyre::{
+    Result as Res,
+    eyre::{bail, eyre},
+};
+use serde::Deserialize;
+
+use crate::core::{data::RecordData, host};
+
+/// a named set of records for one role of a machine, e.g. `work` or `server-base`
+///
+/// # Example
+/// ```toml
+/// [profile.server-base]
+/// tags = ["base"]
+/// records = ["htop"]
+///
+/// [profile.work]
+/// include = ["server-base"]
+/// tags = ["dev"]
+/// hosts = ["work-laptop", "ci-*"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// profiles whose records are part of this one too
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// records with any of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// records with these names
+    #[serde(default)]
+    pub records: Vec<String>,
+    /// hostnames it is used on when none is given, `*` matches any characters
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+pub type Profiles = BTreeMap<String, Profile>;
+
+/// the records of some profiles, with their includes expanded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Members {
+    /// the profiles, includes last
+    pub profiles: Vec<String>,
+    pub tags: BTreeSet<String>,
+    pub records: BTreeSet<String>,
+}
+
+impl Members {
+    /// whether a profile selects `record`, by name or by one of its tags
+    pub fn contains(&self, record: &RecordData) -> bool {
+        self.records.contains(&record.name) || record.tags.iter().any(|t| self.tags.contains(t))
+    }
+
+    fn add(&mut self, profiles: &Profiles, name: &str, path: &mut Vec<String>) -> Res<()> {
+        if path.iter().any(|p| p == name) {
+            bail!(
+                "profile `{}` includes itself: {} → {}",
+                name,
+                path.join(" → "),
+                name
+            );
+        }
+        if self.profiles.iter().any(|p| p == name) {
+            return Ok(());
+        }
+        let profile = profiles.get(name).ok_or_else(|| match profiles.len() {
+            0 => eyre!(
+                "no profile `{}`, add a [profile.{}] table to the config",
+                name,
+                name
+            ),
+            _ => eyre!(
+                "no profile `{}`, the config has {}",
+                name,
+                profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        })?;
+        self.profiles.push(name.to_string());
+        self.tags.extend(profile.tags.iter().cloned());
+        self.records.extend(profile.records.iter().cloned());
+        path.push(name.to_string());
+        for include in &profile.include {
+            self.add(profiles, include, path)?;
+        }
+        path.pop();
+        Ok(())
+    }
+}
+
+/// the records of the profiles `names` and everything they include
+pub fn members(profiles: &Profiles, names: &[String]) -> Res<Members> {
+    let mut members = Members::default();
+    for name in names {
+        members.add(profiles, name, &mut Vec::new())?;
+    }
+    Ok(members)
+}
+
+/// the profiles used on the host `hostname` when none is given
+pub fn for_host(profiles: &Profiles, hostname: &str) -> Vec<String> {
+    profiles
+        .iter()
+        .filter(|(_, p)| p.hosts.iter().any(|h| host::matches(h, hostname)))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn profiles() -> Profiles {
+        toml::from_str(
+            r#"
+[server-base]
+tags = ["base"]
+records = ["htop"]
+
+[work]
+include = ["server-base", "shell"]
+tags = ["dev"]
+hosts = ["work-laptop", "ci-*"]
+
+[shell]
+include = ["server-base"]
+records = ["fish"]
+
+[gaming]
+hosts = ["desk"]
+records = ["steam"]
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_members() -> Res<()> {
+        let profiles = profiles();
+        let work = members(&profiles, &["work".into()])?;
+        assert_eq!(vec!["work", "server-base", "shell"], work.profiles);
+        assert_eq!(
+            BTreeSet::from(["base".to_string(), "dev".to_string()]),
+            work.tags
+        );
+        let record = |name: &str, tags: &[&str]| RecordData {
+            name: name.into(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        };
+        assert!(work.contains(&record("fish", &[])));
+        assert!(work.contains(&record("ripgrep", &["cli", "dev"])));
+        assert!(!work.contains(&record("steam", &[])));
+
+        assert!(members(&profiles, &["nope".into()]).is_err());
+        let mut cyclic = profiles.clone();
+        cyclic.get_mut("server-base").unwrap().include = vec!["shell".into()];
+        let err = members(&cyclic, &["work".into()]).unwrap_err();
+        assert!(err.to_string().contains("includes itself"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_host() {
+        let profiles = profiles();
+        assert_eq!(vec!["work"], for_host(&profiles, "ci-runner-2"));
+        assert_eq!(vec!["gaming"], for_host(&profiles, "desk"));
+        assert!(for_host(&profiles, "server").is_empty());
+    }
+}
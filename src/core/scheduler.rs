@@ -0,0 +1,202 @@
+use std::{
+    collections::VecDeque,
+    io::Write,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use color_eyre::Result as Res;
+
+use crate::core::exec::{self, Output};
+
+/// a single manager command waiting to be run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// name of the package manager, jobs of the same manager never run at the same time
+    pub manager: String,
+    pub argv: Vec<String>,
+    /// kill the process if it runs longer than this
+    pub timeout: Option<Duration>,
+    /// how many more times to try after a failure
+    pub retries: u32,
+    /// wait before the first retry, doubled after each one
+    pub retry_delay: Duration,
+    /// ids of the records this job works on
+    pub records: Vec<u32>,
+}
+
+impl Job {
+    pub fn new(manager: &str, argv: Vec<String>) -> Self {
+        Self {
+            manager: manager.to_string(),
+            argv,
+            timeout: None,
+            retries: 0,
+            retry_delay: Duration::from_secs(1),
+            records: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct JobResult {
+    pub job: Job,
+    /// exit code of the process, `None` if it was terminated by a signal
+    pub status: Res<Option<i32>>,
+    pub output: Output,
+    pub started: DateTime<Utc>,
+    pub duration: Duration,
+}
+
+impl JobResult {
+    pub fn success(&self) -> bool {
+        matches!(self.status, Ok(Some(0)))
+    }
+}
+
+/// runs jobs of different package managers concurrently
+///
+/// jobs of the same manager are kept in their original order and run one after another,
+/// since most managers hold a lock while working
+#[derive(Debug, Clone, Copy)]
+pub struct Scheduler {
+    jobs: usize,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+}
+
+impl Scheduler {
+    /// `jobs` is the max number of managers running at the same time
+    pub fn new(jobs: usize) -> Self {
+        Self { jobs: jobs.max(1) }
+    }
+
+    /// run all jobs, every output line is written to `out` with a `[manager]` prefix
+    ///
+    /// results are returned in the same order as `jobs`
+    pub fn run<W: Write + Send>(&self, jobs: Vec<Job>, out: W) -> Vec<JobResult> {
+        let total = jobs.len();
+        let _forwarding = exec::Forwarding::start();
+        // 按 manager 分组，保持首次出现的顺序
+        let mut groups: Vec<(String, Vec<(usize, Job)>)> = Vec::new();
+        for (idx, job) in jobs.into_iter().enumerate() {
+            match groups.iter_mut().find(|(m, _)| *m == job.manager) {
+                Some((_, group)) => group.push((idx, job)),
+                None => groups.push((job.manager.clone(), vec![(idx, job)])),
+            }
+        }
+
+        let workers = self.jobs.min(groups.len());
+        let queue = Mutex::new(groups.into_iter().collect::<VecDeque<_>>());
+        let results: Mutex<Vec<Option<JobResult>>> = Mutex::new((0..total).map(|_| None).collect());
+        let out = Mutex::new(out);
+
+        thread::scope(|s| {
+            for _ in 0..workers {
+                s.spawn(|| {
+                    loop {
+                        let Some((_, group)) = queue.lock().unwrap().pop_front() else {
+                            break;
+                        };
+                        for (idx, job) in group {
+                            let started = Utc::now();
+                            let timer = Instant::now();
+                            let mut output = Output::default();
+                            let status = exec::run(&job, &out, &mut output);
+                            results.lock().unwrap()[idx] = Some(JobResult {
+                                job,
+                                status,
+                                output,
+                                started,
+                                duration: timer.elapsed(),
+                            });
+                        }
+                    }
+                });
+            }
+        });
+
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.expect("every job is run exactly once"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sh(manager: &str, script: &str) -> Job {
+        Job::new(manager, vec!["sh".into(), "-c".into(), script.into()])
+    }
+
+    #[test]
+    fn test_output_is_prefixed_and_results_ordered() {
+        let mut out = Vec::new();
+        let results = Scheduler::new(2).run(
+            vec![
+                sh("apt", "echo one"),
+                sh("cargo", "echo two; exit 3"),
+                sh("apt", "echo three"),
+            ],
+            &mut out,
+        );
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("[apt] one\n"));
+        assert!(out.contains("[cargo] two\n"));
+        assert!(out.contains("[apt] three\n"));
+        // apt 的两个任务必须按顺序执行
+        assert!(out.find("[apt] one").unwrap() < out.find("[apt] three").unwrap());
+
+        let codes: Vec<_> = results
+            .iter()
+            .map(|r| *r.status.as_ref().unwrap())
+            .collect();
+        assert_eq!(vec![Some(0), Some(3), Some(0)], codes);
+        assert!(!results[1].success());
+        assert_eq!("two\n", results[1].output.stdout);
+    }
+
+    #[test]
+    fn test_different_managers_run_concurrently() {
+        let start = Instant::now();
+        let results = Scheduler::new(2).run(
+            vec![sh("apt", "sleep 0.4"), sh("cargo", "sleep 0.4")],
+            std::io::sink(),
+        );
+        assert!(results.iter().all(JobResult::success));
+        assert!(start.elapsed() < Duration::from_millis(750));
+    }
+
+    #[test]
+    fn test_job_limit_and_same_manager_are_serialized() {
+        let start = Instant::now();
+        Scheduler::new(1).run(
+            vec![sh("apt", "sleep 0.2"), sh("cargo", "sleep 0.2")],
+            std::io::sink(),
+        );
+        Scheduler::new(4).run(
+            vec![sh("apt", "sleep 0.2"), sh("apt", "sleep 0.2")],
+            std::io::sink(),
+        );
+        assert!(start.elapsed() >= Duration::from_millis(800));
+    }
+
+    #[test]
+    fn test_spawn_failure_is_reported() {
+        let results = Scheduler::default().run(
+            vec![Job::new("nope", vec!["fmn-surely-missing-binary".into()])],
+            std::io::sink(),
+        );
+        assert!(results[0].status.is_err());
+    }
+}
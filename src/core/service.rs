@@ -1,32 +1,2933 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
 
-use crate::core::data::RecordData;
+use chrono::Utc;
+use color_eyre::{
+    Result as Res,
+    eyre::{Context, bail, ensure, eyre},
+};
+
+use crate::core::{
+    config::{self, Config, SingleManagerConfig},
+    data::{self, DataBase, DataManager, FlexibleVersion, Query, RecordData},
+    download, exec, git,
+    host::Host,
+    journal::{self, Journal, Transaction},
+    lock::{Locked, Lockfile},
+    manifest::Manifest,
+    oplog::OpLog,
+    outdated::{self, Outdated},
+    parser::{Columns, Format, Listing, OutputParser, Package},
+    privilege::{self, Escalation},
+    scheduler::{Job, JobResult, Scheduler},
+    source::{self, Source},
+    sync,
+    verify::{self, Verified},
+    version::VersionRange,
+};
 
 #[derive(Debug, Clone)]
 pub enum Command {
     /// add a record
     Record(RecordData),
-    /// remove a record
-    Remove(u32),
-    // install via a source
-    // Install(Source, Name),
+    /// remove a record, uninstall it via its source unless `record_only`,
+    /// records of other machines can only be forgotten
+    Remove {
+        id: u32,
+        record_only: bool,
+    },
+    /// install via a source, then record it
+    Install(RecordData),
+    /// install every recorded package, `jobs` overrides the config
+    ///
+    /// pinned packages are installed at their recorded version, or skipped if the manager cannot do that
+    ///
+    /// with a `manifest`, the records are first brought in line with the packages it wants on this host,
+    /// and with `prune` the recorded packages it does not list are removed;
+    /// new ones with a range are installed at a version in it, or nothing is installed
+    ///
+    /// only the records matching `query` are installed, every record if it is empty
+    Apply {
+        query: Query,
+        jobs: Option<usize>,
+        manifest: Option<Manifest>,
+        prune: bool,
+    },
+    /// install the packages of a lockfile at exactly their locked versions, recording them
+    ///
+    /// fails before installing anything if a manager cannot install a given version;
+    /// url and git sources are fetched, checked and placed before any manager runs,
+    /// so a download that does not match the lockfile stops it before the managers change anything
+    ApplyLocked {
+        lockfile: Lockfile,
+        jobs: Option<usize>,
+    },
+    /// upgrade the selected packages
+    ///
+    /// if their manager reports newer versions, only outdated packages are upgraded,
+    /// and a pinned one is installed at the newest version only if that is within its pin,
+    /// versions between the pin and the newest are not looked for;
+    /// otherwise pinned packages are skipped
+    Upgrade {
+        query: Query,
+        jobs: Option<usize>,
+    },
+    /// pin a record to `range`, or to its recorded version, and hold it in its manager
+    Pin {
+        id: u32,
+        range: Option<VersionRange>,
+    },
+    Unpin {
+        id: u32,
+    },
+    /// clean caches of the managers, or of every manager in use if empty
+    Clean {
+        managers: Vec<String>,
+    },
+    /// refresh the package index of the managers, or of every manager in use if empty
+    Refresh {
+        managers: Vec<String>,
+    },
+    /// forget every record
+    Clear,
+    /// revert the last change, `inverse` also runs the opposite manager commands
+    Undo {
+        inverse: bool,
+    },
+    Tag(TagCommand),
+    /// replace a record with an edited version of it, matched by id
+    Update(RecordData),
+    /// merge the records of the sync remote into these, see [`sync::merge`]
+    Pull,
+    /// pull, then upload the records to the sync remote
+    Push,
+}
+
+#[derive(Debug, Clone)]
+pub enum TagCommand {
+    Add {
+        tag: String,
+        query: Query,
+    },
+    Remove {
+        tag: String,
+        query: Query,
+    },
+    /// limited to the records matched by `query`, or every record if it is empty
+    Rename {
+        from: String,
+        to: String,
+        query: Query,
+    },
+}
+
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Record(record) => write!(f, "record {}", record.name),
+            Self::Remove { id, .. } => write!(f, "remove {}", id),
+            Self::Install(record) => match &record.source {
+                Some(source) => write!(f, "install {} {}", record.name, source),
+                None => write!(f, "install {}", record.name),
+            },
+            Self::Apply { manifest: None, .. } => write!(f, "apply"),
+            Self::Apply { prune, .. } => {
+                write!(f, "apply manifest{}", if *prune { " --prune" } else { "" })
+            }
+            Self::ApplyLocked { .. } => write!(f, "apply --locked"),
+            Self::Upgrade { .. } => write!(f, "upgrade"),
+            Self::Pin { id, range } => match range {
+                Some(range) => write!(f, "pin {} {}", id, range),
+                None => write!(f, "pin {}", id),
+            },
+            Self::Unpin { id } => write!(f, "unpin {}", id),
+            Self::Clean { managers } => write!(f, "clean{}", with_spaces(managers)),
+            Self::Refresh { managers } => write!(f, "refresh{}", with_spaces(managers)),
+            Self::Clear => write!(f, "clear"),
+            Self::Undo { .. } => write!(f, "undo"),
+            Self::Update(record) => write!(f, "edit {}", record.id),
+            Self::Pull => write!(f, "pull"),
+            Self::Push => write!(f, "push"),
+            Self::Tag(TagCommand::Add { tag, .. }) => write!(f, "tag add {}", tag),
+            Self::Tag(TagCommand::Remove { tag, .. }) => write!(f, "tag rm {}", tag),
+            Self::Tag(TagCommand::Rename { from, to, .. }) => {
+                write!(f, "tag rename {} {}", from, to)
+            }
+        }
+    }
+}
+
+/// ` a b c`, or nothing if `items` is empty
+fn with_spaces(items: &[String]) -> String {
+    items.iter().map(|i| format!(" {}", i)).collect()
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug)]
 pub struct Manager {
-    index: HashMap<u32, RecordData>,
+    config: Config,
+    data: DataManager,
+    log: OpLog,
+    journal: Journal,
+    /// this machine, new records are stamped with it
+    host: Host,
+    /// the git repository records are synced through
+    sync_dir: PathBuf,
+    /// the remote commit merged by a pull or made by a push,
+    /// it becomes the merge base once the records are saved
+    pulled: Option<String>,
+    /// local records a pull moved to another id, `(from, to)`
+    renumbered: Vec<(u32, u32)>,
 }
 
 impl Manager {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(
+        config: Config,
+        data: DataManager,
+        log: OpLog,
+        journal: Journal,
+        sync_dir: &Path,
+    ) -> Self {
+        let host = Host::current(config.host.as_deref());
+        Self {
+            config,
+            data,
+            log,
+            journal,
+            host,
+            sync_dir: sync_dir.to_path_buf(),
+            pulled: None,
+            renumbered: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn log(&self) -> &OpLog {
+        &self.log
+    }
+
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    pub fn records(&self) -> Vec<&RecordData> {
+        self.data.db.to_vec()
+    }
+
+    /// whether `record` was recorded on this machine, records without a host are everywhere
+    pub fn is_here(&self, record: &RecordData) -> bool {
+        record.host.as_ref().is_none_or(|h| h.is(&self.host))
+    }
+
+    /// add a new record, on this machine unless it names another
+    fn insert(&mut self, mut record: RecordData) -> Res<u32> {
+        record.host.get_or_insert_with(|| self.host.clone());
+        self.data.db.insert(record)
+    }
+
+    pub fn resolve(&self, name_or_id: &str) -> Res<&RecordData> {
+        self.data.db.resolve(name_or_id)
+    }
+
+    pub fn tag_counts(&self) -> BTreeMap<&str, usize> {
+        self.data.db.tag_counts()
+    }
+
+    pub fn execute(&mut self, command: Command) -> Res<()> {
+        let mut before = self.data.db.data.clone();
+        let mut description = command.to_string();
+        // 同步来的记录保留原来的时间
+        let synced = matches!(command, Command::Pull | Command::Push);
+        let (operations, undoes) = match command {
+            Command::Undo { inverse } => {
+                let tx = self
+                    .journal
+                    .last_undoable()?
+                    .ok_or_else(|| eyre!("nothing to undo"))?;
+                description = format!("undo `{}`", tx.command);
+                (self.undo(&tx, inverse)?, Some(tx.id))
+            }
+            command => match self.apply(command) {
+                Ok(operations) => (operations, None),
+                Err(e) => {
+                    // 失败的命令不留下改了一半的记录
+                    self.data.db.data = before;
+                    self.pulled = None;
+                    self.renumbered.clear();
+                    return Err(e);
+                }
+            },
+        };
+
+        if !synced {
+            let now = Utc::now();
+            for (id, record) in self.data.db.data.iter_mut() {
+                if before.get(id) != Some(record) {
+                    record.updated = Some(now);
+                }
+            }
+        }
+        // 合并时换了 id 的本地记录，连同它的历史一起搬过去
+        for (from, to) in std::mem::take(&mut self.renumbered) {
+            if let Some(record) = before.remove(&from) {
+                before.insert(to, RecordData { id: to, ..record });
+            }
+            self.journal.renumber(from, to)?;
+            self.log.renumber(from, to)?;
+        }
+        let changes = journal::diff(&before, &self.data.db.data);
+        if !changes.is_empty() || undoes.is_some() {
+            self.journal.append(Transaction {
+                id: 0,
+                timestamp: Utc::now(),
+                command: description,
+                operations,
+                changes,
+                undoes,
+            })?;
+        }
+        self.data.save()?;
+        // 记录存下来以后才算合并完，下次从这里合并
+        if let Some(commit) = self.pulled.take() {
+            self.sync_git(&["update-ref", sync::BASE_REF, &commit])?;
+        }
+        Ok(())
+    }
+
+    /// run a command, returns the ids of the operations it ran
+    fn apply(&mut self, command: Command) -> Res<Vec<u32>> {
+        let mut operations = Vec::new();
+        match command {
+            Command::Record(record) => {
+                self.insert(record)?;
+            }
+            Command::Remove { id, record_only } => {
+                let record = self
+                    .data
+                    .db
+                    .data
+                    .get(&id)
+                    .ok_or_else(|| eyre!("no record with id {}", id))?;
+                if !record_only
+                    && let Some(host) = record.host.as_ref().filter(|_| !self.is_here(record))
+                {
+                    bail!(
+                        "`{}` was recorded on {}, remove it there, or pass --record-only to only drop the record",
+                        record.name,
+                        host
+                    );
+                }
+                if !record_only && record.source.as_deref().is_some_and(Self::is_builtin) {
+                    // 只删 fmn 自己放进去的文件，别的地方的可能是系统装的
+                    match &record.location {
+                        Some(location) if location.exists() && self.placed(record, location)? => {
+                            fs::remove_file(location)?
+                        }
+                        _ => eprintln!(
+                            "nothing was deleted for `{}`, no installed file is recorded, remove its files yourself",
+                            record.name
+                        ),
+                    }
+                } else if !record_only && let Some(source) = &record.source {
+                    let mut job = self.job(source, |c| &c.remove, &record.name)?;
+                    job.records.push(id);
+                    operations = self.run(vec![job], None)?;
+                }
+                self.data.db.data.remove(&id);
+            }
+            Command::Clear => {
+                self.data.db.data.clear();
+            }
+            Command::Install(record) => {
+                record.validate()?;
+                let source = record
+                    .source
+                    .clone()
+                    .ok_or_else(|| eyre!("a source is required to install `{}`", record.name))?;
+                let id = self.install_record(record)?;
+                if Self::is_builtin(&source) {
+                    let mut record = self.data.db.data[&id].clone();
+                    operations = self.install_builtin(&mut record, id, true)?;
+                    record.installation_date.get_or_insert_with(Utc::now);
+                    self.data.db.data.insert(id, record);
+                    return Ok(operations);
+                }
+                let record = &self.data.db.data[&id];
+                if record.version.is_some() || record.pin.is_some() {
+                    ensure!(
+                        self.manager_config(&source)?.install_version.is_some(),
+                        "`{}` cannot install a given version, it has no install_version command",
+                        source
+                    );
+                }
+                // `@14.1` 就装 14.1，范围就挑一个范围里的版本
+                if let Some(exact) = record.pin.as_ref().and_then(|p| p.as_exact()).cloned() {
+                    self.data.db.data.get_mut(&id).unwrap().version = Some(exact);
+                }
+                self.pick_version(id)?;
+                let record = &self.data.db.data[&id];
+                let jobs = match &record.version {
+                    Some(_) if record.pin.is_some() => self.pinned_install(record, &source)?,
+                    Some(version) => {
+                        let install = self.manager_config(&source)?.install_version.as_ref();
+                        let version = version.to_string();
+                        let mut job =
+                            self.install_job(&source, install.unwrap(), record, Some(&version))?;
+                        job.records.push(id);
+                        vec![job]
+                    }
+                    None => {
+                        let install = &self.manager_config(&source)?.install;
+                        let mut job = self.install_job(&source, install, record, None)?;
+                        job.records.push(id);
+                        vec![job]
+                    }
+                };
+                operations = self.run(jobs, None)?;
+                self.refresh_versions(&[id]);
+                let record = &self.data.db.data[&id];
+                if record.location.is_none() {
+                    let name = record.name.clone();
+                    let (location, ops) = self.locate(&source, &name, id);
+                    self.data.db.data.get_mut(&id).unwrap().location = location;
+                    operations.extend(ops);
+                }
+                let record = self.data.db.data.get_mut(&id).unwrap();
+                record.installation_date.get_or_insert_with(Utc::now);
+            }
+            Command::Apply {
+                query,
+                jobs,
+                manifest,
+                prune,
+            } => {
+                // 清单里新加的记录
+                let new = match manifest {
+                    Some(manifest) => {
+                        let (new, ops) = self.reconcile(&manifest, prune)?;
+                        operations = ops;
+                        new
+                    }
+                    None => Vec::new(),
+                };
+                for id in &new {
+                    self.pick_version(*id)?;
+                }
+                let mut pending = Vec::new();
+                let mut builtin = Vec::new();
+                let mut unlocated = Vec::new();
+                for record in self.records() {
+                    let Some(source) = &record.source else {
+                        continue;
+                    };
+                    if !self.is_here(record) || !query.is_empty() && !query.matches(record) {
+                        continue;
+                    }
+                    if Self::is_builtin(source) {
+                        // 已经装好的不再重新下载或构建
+                        if !record.location.as_ref().is_some_and(|l| l.exists()) {
+                            builtin.push(record.id);
+                        }
+                        continue;
+                    }
+                    if record.location.is_none() {
+                        unlocated.push(record.id);
+                    }
+                    if record.pin.is_some() {
+                        pending.extend(self.pinned_install(record, source)?);
+                        continue;
+                    }
+                    let install = &self.manager_config(source)?.install;
+                    let mut job = self.install_job(source, install, record, None)?;
+                    job.records.push(record.id);
+                    pending.push(job);
+                }
+                operations.extend(self.run(pending, jobs)?);
+                for id in unlocated {
+                    let record = &self.data.db.data[&id];
+                    let source = record.source.clone().unwrap_or_default();
+                    let (location, ops) = self.locate(&source, &record.name, id);
+                    self.data.db.data.get_mut(&id).unwrap().location = location;
+                    operations.extend(ops);
+                }
+                for id in builtin {
+                    let mut record = self.data.db.data[&id].clone();
+                    operations.extend(self.install_builtin(&mut record, id, false)?);
+                    self.data.db.data.insert(id, record);
+                }
+                for id in &new {
+                    let record = self.data.db.data.get_mut(id).unwrap();
+                    record.installation_date.get_or_insert_with(Utc::now);
+                }
+                self.fill_versions(&new);
+            }
+            Command::ApplyLocked { lockfile, jobs } => {
+                operations = self.apply_locked(&lockfile, jobs)?;
+            }
+            Command::Upgrade { query, jobs } => {
+                let ids = self.select_here(&query)?;
+                let (outdated, asked, ops) = self.find_outdated(&ids)?;
+                operations = ops;
+                let mut pending = Vec::new();
+                let mut upgraded = Vec::new();
+                let mut rebuilds = Vec::new();
+                // 升级到哪个版本要装完再问管理器
+                let mut unknown = Vec::new();
+                for id in ids {
+                    let record = &self.data.db.data[&id];
+                    let Some(source) = &record.source else {
+                        continue;
+                    };
+                    if source == source::GIT {
+                        if let Some(pin) = &record.pin {
+                            eprintln!("skipped `{}`, it is pinned to {}", record.name, pin);
+                        } else {
+                            rebuilds.push(id);
+                        }
+                        continue;
+                    }
+                    if source == source::URL {
+                        eprintln!(
+                            "skipped `{}`, url sources are not upgraded, install the url of a newer release",
+                            record.name
+                        );
+                        continue;
+                    }
+                    if !asked.contains(source) {
+                        // 管理器不报告可用版本，没法知道会不会越过 pin
+                        if let Some(pin) = &record.pin {
+                            eprintln!("skipped `{}`, it is pinned to {}", record.name, pin);
+                            continue;
+                        }
+                        let mut job = self.job(source, |c| &c.upgrade, &record.name)?;
+                        job.records.push(id);
+                        pending.push(job);
+                        unknown.push(id);
+                        continue;
+                    }
+                    let Some(newer) = outdated.iter().find(|o| o.id == id) else {
+                        continue;
+                    };
+                    let mut job = match &record.pin {
+                        None => self.job(source, |c| &c.upgrade, &record.name)?,
+                        Some(pin) if !newer.allowed() => {
+                            eprintln!(
+                                "skipped `{}`, {} is not in its pin {}",
+                                record.name, newer.available, pin
+                            );
+                            continue;
+                        }
+                        Some(_) => {
+                            let Some(install) = &self.manager_config(source)?.install_version
+                            else {
+                                eprintln!(
+                                    "skipped `{}`, it is pinned but `{}` has no install_version command",
+                                    record.name, source
+                                );
+                                continue;
+                            };
+                            let version = newer.available.to_string();
+                            self.job_with(source, install, &record.name, Some(&version))?
+                        }
+                    };
+                    job.records.push(id);
+                    pending.push(job);
+                    upgraded.push((id, newer.available.clone()));
+                }
+                operations.extend(self.run(pending, jobs)?);
+                for (id, version) in upgraded {
+                    self.data.db.data.get_mut(&id).unwrap().version = Some(version);
+                }
+                self.refresh_versions(&unknown);
+                for id in rebuilds {
+                    let mut record = self.data.db.data[&id].clone();
+                    operations.extend(self.install_builtin(&mut record, id, true)?);
+                    self.data.db.data.insert(id, record);
+                }
+            }
+            Command::Pin { id, range } => operations = self.pin(id, range)?,
+            Command::Unpin { id } => operations = self.unpin(id)?,
+            Command::Clean { managers } => {
+                let jobs = self.maintained(managers, "clean", |c| c.clean.as_ref())?;
+                operations = self.run(jobs, None)?;
+            }
+            Command::Refresh { managers } => {
+                let jobs = self.maintained(managers, "refresh", |c| c.refresh.as_ref())?;
+                operations = self.run(jobs, None)?;
+            }
+            Command::Tag(command) => self.tag(command)?,
+            Command::Update(record) => {
+                ensure!(
+                    self.data.db.data.contains_key(&record.id),
+                    "no record with id {}",
+                    record.id
+                );
+                record.validate()?;
+                self.data.db.data.insert(record.id, record);
+            }
+            Command::Pull => operations = self.pull()?,
+            Command::Push => {
+                operations = self.pull()?;
+                operations.extend(self.push()?);
+            }
+            Command::Undo { .. } => unreachable!("undo is handled by execute"),
+        }
+        Ok(operations)
+    }
+
+    /// record and install every package of `lockfile` at its locked version
+    ///
+    /// installed git and url sources are kept if they are at the locked commit or checksum
+    fn apply_locked(&mut self, lockfile: &Lockfile, jobs: Option<usize>) -> Res<Vec<u32>> {
+        let mut pending = Vec::new();
+        let mut builtin = Vec::new();
+        let mut ids = Vec::new();
+        // 先检查全部，有一个装不了就什么都不装
+        for locked in &lockfile.packages {
+            let mut record = locked.record()?;
+            let existing = self
+                .records()
+                .into_iter()
+                .find(|r| r.source == record.source && r.name == record.name)
+                .cloned();
+            let id = match existing {
+                Some(mut existing) => {
+                    let installed = existing.version == record.version
+                        && existing.location.as_ref().is_some_and(|l| l.exists())
+                        && (existing.checksum.is_none() || existing.checksum == record.checksum);
+                    let id = existing.id;
+                    existing.version = record.version.take();
+                    existing.origin = record.origin.take();
+                    existing.sha256 = record.sha256.take();
+                    self.data.db.data.insert(id, existing);
+                    if installed && record.source.as_deref().is_some_and(Self::is_builtin) {
+                        ids.push(id);
+                        continue;
+                    }
+                    id
+                }
+                None => {
+                    let id = self.data.db.next_id();
+                    self.insert(record)?;
+                    id
+                }
+            };
+            ids.push(id);
+            let record = &self.data.db.data[&id];
+            let source = record.source.clone().unwrap_or_default();
+            if Self::is_builtin(&source) {
+                builtin.push(id);
+                continue;
+            }
+            let install = self.manager_config(&source)?.install_version.as_ref();
+            let install = install.ok_or_else(|| {
+                eyre!(
+                    "`{}` cannot install a given version, it has no install_version command",
+                    source
+                )
+            })?;
+            let version = record.version.as_ref().map(|v| v.to_string());
+            let mut job = self.install_job(&source, install, record, version.as_deref())?;
+            job.records.push(id);
+            pending.push(job);
+        }
+
+        let mut operations = Vec::new();
+        for id in builtin {
+            let mut record = self.data.db.data[&id].clone();
+            let locked = record.checksum.take();
+            operations.extend(self.install_builtin(&mut record, id, false)?);
+            // 构建不一定可重现，只有下载的文件能按校验和比较
+            if record.source.as_deref() == Some(source::URL) {
+                ensure!(
+                    locked.is_none() || locked == record.checksum,
+                    "the installed file of `{}` does not match the checksum in the lockfile",
+                    record.name
+                );
+            }
+            self.data.db.data.insert(id, record);
+        }
+        operations.extend(self.run(pending, jobs)?);
+        for id in ids {
+            let record = &self.data.db.data[&id];
+            if record.location.is_none() {
+                let source = record.source.clone().unwrap_or_default();
+                let (location, ops) = self.locate(&source, &record.name, id);
+                operations.extend(ops);
+                self.data.db.data.get_mut(&id).unwrap().location = location;
+            }
+            let record = self.data.db.data.get_mut(&id).unwrap();
+            record.installation_date.get_or_insert_with(Utc::now);
+        }
+        Ok(operations)
+    }
+
+    /// the id of this host's record of the package `record` is of, updated with what it gives,
+    /// or of `record` inserted if there is none
+    fn install_record(&mut self, record: RecordData) -> Res<u32> {
+        let existing = self
+            .records()
+            .into_iter()
+            .find(|r| self.is_here(r) && r.source == record.source && r.name == record.name)
+            .map(|r| r.id);
+        let Some(id) = existing else {
+            return self.insert(record);
+        };
+        let existing = self.data.db.data.get_mut(&id).unwrap();
+        existing.version = record.version;
+        if record.pin.is_some() {
+            existing.pin = record.pin;
+        }
+        existing.origin = record.origin.or(existing.origin.take());
+        existing.sha256 = record.sha256.or(existing.sha256.take());
+        Ok(id)
+    }
+
+    /// pin the record `id` to `range`, or to its recorded version, and hold it in its manager
+    fn pin(&mut self, id: u32, range: Option<VersionRange>) -> Res<Vec<u32>> {
+        let record = self
+            .data
+            .db
+            .data
+            .get(&id)
+            .ok_or_else(|| eyre!("no record with id {}", id))?;
+        let range = match (range, &record.version) {
+            (Some(range), Some(version)) => {
+                ensure!(
+                    range.matches(version),
+                    "the recorded version {} of `{}` is not in `{}`",
+                    version,
+                    record.name,
+                    range
+                );
+                range
+            }
+            (Some(range), None) => range,
+            (None, Some(version)) => VersionRange::exact(version.clone()),
+            (None, None) => bail!(
+                "`{}` has no recorded version, give the version or range to pin it to",
+                record.name
+            ),
+        };
+        let operations = self.hold(id, |c| c.hold.as_ref())?;
+        self.data.db.data.get_mut(&id).unwrap().pin = Some(range);
+        Ok(operations)
+    }
+
+    /// drop the pin of the record `id` and release its hold
+    fn unpin(&mut self, id: u32) -> Res<Vec<u32>> {
+        let record = self
+            .data
+            .db
+            .data
+            .get(&id)
+            .ok_or_else(|| eyre!("no record with id {}", id))?;
+        ensure!(record.pin.is_some(), "`{}` is not pinned", record.name);
+        let operations = self.hold(id, |c| c.unhold.as_ref())?;
+        self.data.db.data.get_mut(&id).unwrap().pin = None;
+        Ok(operations)
+    }
+
+    /// make the records match the packages `manifest` wants on this host
+    ///
+    /// pins, origins and sha256 follow the manifest, its tags are added, the other tags are kept;
+    /// pins are changed like `fmn pin` and `fmn unpin` do, records without a source are not pruned;
+    /// returns the ids of the records it added, and the operations run to pin and prune
+    fn reconcile(&mut self, manifest: &Manifest, prune: bool) -> Res<(Vec<u32>, Vec<u32>)> {
+        let mut new = Vec::new();
+        let mut wanted = BTreeSet::new();
+        let mut pins = Vec::new();
+        for (source, spec) in manifest.here() {
+            // 别的机器上的同名记录不动，这台机器另加一条
+            let existing = self
+                .records()
+                .into_iter()
+                .find(|r| {
+                    self.is_here(r)
+                        && r.source.as_deref() == Some(source.manager())
+                        && r.name == source.name()
+                })
+                .map(|r| r.id);
+            let Some(record) = existing.and_then(|id| self.data.db.data.get_mut(&id)) else {
+                let record = spec.record(source);
+                record.validate()?;
+                let id = self.data.db.next_id();
+                self.insert(record)?;
+                new.push(id);
+                wanted.insert(id);
+                continue;
+            };
+            wanted.insert(record.id);
+            if record.pin != spec.version {
+                if let Some(exact) = spec.version.as_ref().and_then(|v| v.as_exact()) {
+                    record.version = Some(exact.clone());
+                }
+                pins.push((record.id, spec.version.clone()));
+            }
+            if let Some(origin) = source.origin() {
+                record.origin = Some(origin);
+            }
+            for tag in &spec.tags {
+                record.add_tag(tag);
+            }
+            if spec.description.is_some() {
+                record.description = spec.description.clone();
+            }
+            if spec.sha256.is_some() {
+                record.sha256 = spec.sha256.clone();
+            }
+        }
+
+        let mut operations = Vec::new();
+        for (id, range) in pins {
+            operations.extend(match range {
+                Some(range) => self.pin(id, Some(range))?,
+                None => self.unpin(id)?,
+            });
+        }
+        // 没有来源的记录没装过什么，不用清掉
+        let extra: Vec<u32> = self
+            .records()
+            .iter()
+            .filter(|r| r.source.is_some() && self.is_here(r) && !wanted.contains(&r.id))
+            .map(|r| r.id)
+            .collect();
+        for id in extra {
+            if prune {
+                operations.extend(self.apply(Command::Remove {
+                    id,
+                    record_only: false,
+                })?);
+            } else {
+                eprintln!(
+                    "`{}` is recorded but not in the manifest, --prune removes it",
+                    self.data.db.data[&id].name
+                );
+            }
+        }
+        Ok((new, operations))
+    }
+
+    /// set the version of the new pinned record `id` to the newest one in its pin
+    /// that its manager lists as installed or finds by `search`
+    ///
+    /// fails if there is none, or the manager cannot install a given version
+    fn pick_version(&mut self, id: u32) -> Res<()> {
+        let record = &self.data.db.data[&id];
+        let (Some(source), Some(pin)) = (&record.source, &record.pin) else {
+            return Ok(());
+        };
+        if record.version.is_some() || Self::is_builtin(source) {
+            return Ok(());
+        }
+        ensure!(
+            self.manager_config(source)?.install_version.is_some(),
+            "`{}` wants {} but `{}` has no install_version command to install it at",
+            record.name,
+            pin,
+            source
+        );
+        let installed = self.installed(source).unwrap_or_default();
+        let found = match self.search(source, &record.name) {
+            Ok(Listing::Packages(packages)) => packages,
+            _ => Vec::new(),
+        };
+        let version = installed
+            .into_iter()
+            .chain(found)
+            .filter(|p| p.name == record.name)
+            .filter_map(|p| p.version)
+            .filter(|v| pin.matches(v))
+            .max()
+            .ok_or_else(|| {
+                eyre!(
+                    "no version of `{}` in {} is installed or found by `{}`, give an exact version",
+                    record.name,
+                    pin,
+                    source
+                )
+            })?;
+        self.data.db.data.get_mut(&id).unwrap().version = Some(version);
+        Ok(())
+    }
+
+    /// set the versions of the records `ids` that have none, from what their managers list as installed
+    fn fill_versions(&mut self, ids: &[u32]) {
+        let sources: BTreeSet<String> = ids
+            .iter()
+            .map(|id| &self.data.db.data[id])
+            .filter(|r| r.version.is_none())
+            .filter_map(|r| r.source.clone())
+            .filter(|s| !Self::is_builtin(s))
+            .collect();
+        for source in sources {
+            let Ok(packages) = self.installed(&source) else {
+                continue;
+            };
+            for id in ids {
+                let record = self.data.db.data.get_mut(id).unwrap();
+                if record.version.is_some() || record.source.as_ref() != Some(&source) {
+                    continue;
+                }
+                record.version = packages
+                    .iter()
+                    .find(|p| p.name == record.name)
+                    .and_then(|p| p.version.clone());
+            }
+        }
+    }
+
+    /// set the versions of the records `ids` to what their managers list as installed,
+    /// those not listed keep the version they had
+    fn refresh_versions(&mut self, ids: &[u32]) {
+        let previous: Vec<_> = ids
+            .iter()
+            .map(|id| self.data.db.data.get_mut(id).unwrap().version.take())
+            .collect();
+        self.fill_versions(ids);
+        for (id, version) in ids.iter().zip(previous) {
+            let record = self.data.db.data.get_mut(id).unwrap();
+            if record.version.is_none() {
+                record.version = version;
+            }
+        }
+    }
+
+    fn tag(&mut self, command: TagCommand) -> Res<()> {
+        match command {
+            TagCommand::Add { tag, query } => {
+                data::validate_tag(&tag)?;
+                for id in self.select(&query)? {
+                    self.data.db.data.get_mut(&id).unwrap().add_tag(&tag);
+                }
+            }
+            TagCommand::Remove { tag, query } => {
+                for id in self.select(&query)? {
+                    self.data.db.data.get_mut(&id).unwrap().remove_tag(&tag);
+                }
+            }
+            TagCommand::Rename { from, to, query } => {
+                data::validate_tag(&to)?;
+                let mut renamed = 0;
+                for record in self.data.db.data.values_mut() {
+                    if !(query.is_empty() || query.matches(record)) {
+                        continue;
+                    }
+                    if let Some(pos) = record.tags.iter().position(|t| *t == from) {
+                        // 保持原来的位置，如果新标签已经存在就只删掉旧的
+                        if record.tags.contains(&to) {
+                            record.tags.remove(pos);
+                        } else {
+                            record.tags[pos] = to.clone();
+                        }
+                        renamed += 1;
+                    }
+                }
+                ensure!(renamed > 0, "no record is tagged `{}`", from);
+            }
+        }
+        Ok(())
+    }
+
+    /// ids of the records matched by `query`, fails if there are none
+    fn select(&self, query: &Query) -> Res<Vec<u32>> {
+        ensure!(
+            !query.is_empty(),
+            "no records selected, name some records or pass --all"
+        );
+        let ids = self.data.db.select(query);
+        ensure!(!ids.is_empty(), "no record matches");
+        Ok(ids)
+    }
+
+    /// [`Manager::select`], without the records of other machines
+    fn select_here(&self, query: &Query) -> Res<Vec<u32>> {
+        let ids: Vec<u32> = self
+            .select(query)?
+            .into_iter()
+            .filter(|id| self.is_here(&self.data.db.data[id]))
+            .collect();
+        ensure!(!ids.is_empty(), "no record on this host matches");
+        Ok(ids)
+    }
+
+    /// restore the before-images of a transaction
+    ///
+    /// with `inverse`, packages it installed are removed and packages it removed are installed again
+    fn undo(&mut self, tx: &Transaction, inverse: bool) -> Res<Vec<u32>> {
+        let mut operations = Vec::new();
+        if inverse && !tx.operations.is_empty() {
+            let mut jobs = Vec::new();
+            for change in &tx.changes {
+                let (record, command): (_, fn(&SingleManagerConfig) -> &config::Command) =
+                    match (&change.before, &change.after) {
+                        (None, Some(added)) => (added, |c| &c.remove),
+                        (Some(removed), None) => (removed, |c| &c.install),
+                        _ => continue,
+                    };
+                let Some(source) = &record.source else {
+                    continue;
+                };
+                if let Some(host) = record.host.as_ref().filter(|_| !self.is_here(record)) {
+                    eprintln!(
+                        "skipped `{}`, it was recorded on {}, undo it there",
+                        record.name, host
+                    );
+                    continue;
+                }
+                if Self::is_builtin(source) {
+                    eprintln!(
+                        "skipped `{}`, {} sources are not undone",
+                        record.name, source
+                    );
+                    continue;
+                }
+                let mut job = self.job(source, command, &record.name)?;
+                job.records.push(change.id);
+                jobs.push(job);
+            }
+            operations = self.run(jobs, None)?;
+        }
+
+        for change in &tx.changes {
+            match &change.before {
+                Some(record) => self.data.db.data.insert(change.id, record.clone()),
+                None => self.data.db.data.remove(&change.id),
+            };
+        }
+        Ok(operations)
+    }
+
+    /// search the packages of `source` for `term`
+    pub fn search(&self, source: &str, term: &str) -> Res<Listing> {
+        self.ask(source, "search", |c| c.search.as_ref(), term, None)
+    }
+
+    /// details of a recorded package, from its manager
+    pub fn info(&self, name_or_id: &str) -> Res<Listing> {
+        let record = self.resolve(name_or_id)?;
+        let source = record
+            .source
+            .as_ref()
+            .ok_or_else(|| eyre!("`{}` has no source", record.name))?;
+        self.ask(source, "info", |c| c.info.as_ref(), &record.name, None)
+    }
+
+    /// packages installed by `source`
+    pub fn installed(&self, source: &str) -> Res<Vec<Package>> {
+        let default = OutputParser::from(Format::Columns(Columns::default()));
+        match self.ask(source, "list", |c| c.list.as_ref(), "", Some(default))? {
+            Listing::Packages(packages) => Ok(packages),
+            Listing::Raw(_) => unreachable!("list always has a parser"),
+        }
+    }
+
+    /// run a command of `source` that only reads, and parse its output
+    ///
+    /// the output is kept as is if the command has no parser and no `default` is given
+    fn ask(
+        &self,
+        source: &str,
+        verb: &str,
+        command: impl Fn(&SingleManagerConfig) -> Option<&config::Command>,
+        package_name: &str,
+        default: Option<OutputParser>,
+    ) -> Res<Listing> {
+        let command = command(self.manager_config(source)?)
+            .ok_or_else(|| eyre!("`{}` has no {} command", source, verb))?;
+        let job = self.query_job(source, command, package_name)?;
+        let (_, mut results) = self.run_with(vec![job], None, io::sink())?;
+        let stdout = results.remove(0).output.stdout;
+        match command.parser().or(default.as_ref()) {
+            Some(parser) => Ok(Listing::Packages(parser.parse(&stdout)?)),
+            None => Ok(Listing::Raw(stdout)),
+        }
+    }
+
+    /// managers to run `command` of, the ones named or those with records that have the command
+    fn maintained(
+        &self,
+        managers: Vec<String>,
+        verb: &str,
+        command: impl Fn(&SingleManagerConfig) -> Option<&config::Command>,
+    ) -> Res<Vec<Job>> {
+        let managers: BTreeSet<String> = if managers.is_empty() {
+            self.records()
+                .iter()
+                .filter_map(|r| r.source.clone())
+                .filter(|s| {
+                    self.config
+                        .manager
+                        .config_of(s)
+                        .is_some_and(|c| command(c).is_some())
+                })
+                .collect()
+        } else {
+            managers.into_iter().collect()
+        };
+        managers
+            .iter()
+            .map(|source| {
+                let command = command(self.manager_config(source)?)
+                    .ok_or_else(|| eyre!("`{}` has no {} command", source, verb))?;
+                self.job_with(source, command, "", None)
+            })
+            .collect()
+    }
+
+    /// recorded packages with a newer version, all of them if `query` is empty
+    pub fn outdated(&self, query: &Query) -> Res<Vec<Outdated>> {
+        let ids = match query.is_empty() {
+            true => self
+                .records()
+                .iter()
+                .filter(|r| self.is_here(r))
+                .map(|r| r.id)
+                .collect(),
+            false => self.select_here(query)?,
+        };
+        Ok(self.find_outdated(&ids)?.0)
+    }
+
+    /// the exact versions of every package with a source recorded on this machine
+    ///
+    /// versions come from what the managers list as installed, or else the records;
+    /// fails if a package has no known version
+    pub fn lock(&self) -> Res<Lockfile> {
+        let records: Vec<&RecordData> = self
+            .records()
+            .into_iter()
+            .filter(|r| r.source.is_some() && self.is_here(r))
+            .collect();
+        let sources: BTreeSet<&str> = records
+            .iter()
+            .filter_map(|r| r.source.as_deref())
+            .filter(|s| !Self::is_builtin(s))
+            .filter(|s| {
+                self.config
+                    .manager
+                    .config_of(s)
+                    .is_some_and(|c| c.list.is_some())
+            })
+            .collect();
+        let mut installed = HashMap::new();
+        for source in sources {
+            match self.installed(source) {
+                Ok(packages) => {
+                    installed.insert(source, packages);
+                }
+                Err(e) => eprintln!(
+                    "cannot list what `{}` installed, using the recorded versions: {}",
+                    source, e
+                ),
+            }
+        }
+
+        let mut packages = Vec::new();
+        let mut unknown = Vec::new();
+        for record in records {
+            let version = record
+                .source
+                .as_deref()
+                .and_then(|s| installed.get(s))
+                .and_then(|packages| packages.iter().find(|p| p.name == record.name))
+                .and_then(|p| p.version.as_ref())
+                .or(record.version.as_ref());
+            let is_url = record.source.as_deref() == Some(source::URL);
+            if version.is_none() && !is_url {
+                unknown.push(record.name.as_str());
+                continue;
+            }
+            packages.extend(Locked::new(record, version));
+        }
+        ensure!(
+            unknown.is_empty(),
+            "no version is known for {}, record them with `fmn set <record> version=<version>`",
+            unknown.join(", ")
+        );
+        Ok(Lockfile::new(packages))
+    }
+
+    /// check the recorded locations of the selected packages, all of them if `query` is empty,
+    /// skipping those recorded on other machines
+    pub fn verify(&self, query: &Query) -> Res<Vec<Verified>> {
+        let records = match query.is_empty() {
+            true => self.records(),
+            false => self
+                .select(query)?
+                .iter()
+                .map(|id| &self.data.db.data[id])
+                .collect(),
+        };
+        // 别的机器上的位置在这里没有意义
+        Ok(records
+            .into_iter()
+            .filter(|r| self.is_here(r))
+            .map(verify::check)
+            .collect())
+    }
+
+    /// sync through the git repository at `url`, replacing the remote if there is one
+    pub fn add_remote(&self, url: &str) -> Res<()> {
+        if let Some(old) = self.remote()? {
+            if old == url {
+                return Ok(());
+            }
+            // 上次同步的状态属于旧的仓库，不能拿来和新的合并
+            fs::remove_dir_all(&self.sync_dir)?;
+            println!("replaced the remote `{}`", old);
+        }
+        fs::create_dir_all(&self.sync_dir)?;
+        self.sync_git(&["init", "--quiet", "--initial-branch", sync::BRANCH])?;
+        self.sync_git(&["config", "remote.origin.url", url])?;
+        self.sync_git(&[
+            "config",
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/remotes/origin/*",
+        ])?;
+        Ok(())
+    }
+
+    /// the url of the sync remote, if one was added
+    pub fn remote(&self) -> Res<Option<String>> {
+        if !self.sync_dir.join(".git").exists() {
+            return Ok(None);
+        }
+        let (_, url) = self.sync_git(&["config", "--default", "", "--get", "remote.origin.url"])?;
+        Ok(Some(url).filter(|u| !u.is_empty()))
+    }
+
+    /// run git in the sync repository, returns the operation and what it printed
+    fn sync_git(&self, args: &[&str]) -> Res<(Vec<u32>, String)> {
+        let (operations, results) =
+            self.run_with(vec![git::job(&self.sync_dir, args)?], None, io::sink())?;
+        Ok((operations, results[0].output.stdout.trim().to_string()))
+    }
+
+    /// the commit `reference` points to in the sync repository, if it exists
+    fn sync_commit(&self, reference: &str) -> Res<(Vec<u32>, Option<String>)> {
+        let (operations, commit) =
+            self.sync_git(&["for-each-ref", "--format=%(objectname)", reference])?;
+        Ok((operations, Some(commit).filter(|c| !c.is_empty())))
+    }
+
+    /// the records in the sync file of `commit`
+    fn synced_records(&self, commit: &str) -> Res<(Vec<u32>, DataBase)> {
+        let (operations, text) = self.sync_git(&["show", &format!("{}:{}", commit, sync::FILE)])?;
+        let records =
+            DataBase::from_json(&text).with_context(|| format!("invalid {}", sync::FILE))?;
+        Ok((operations, records))
+    }
+
+    /// merge the records of the remote into these, against the remote commit
+    /// merged last time, see [`sync::BASE_REF`]
+    fn pull(&mut self) -> Res<Vec<u32>> {
+        let url = self.remote()?.ok_or_else(|| {
+            eyre!("nothing to sync with, add a remote with `fmn remote add <url>`")
+        })?;
+        let mut operations = self.sync_git(&["fetch", "--quiet", "origin"])?.0;
+        let branch = format!("refs/remotes/origin/{}", sync::BRANCH);
+        let (ops, head) = self.sync_commit(&branch)?;
+        operations.extend(ops);
+        let Some(head) = head else {
+            println!("`{}` has no records yet", url);
+            return Ok(operations);
+        };
+        let (ops, base) = self.sync_commit(sync::BASE_REF)?;
+        operations.extend(ops);
+        let base = match base {
+            Some(base) => {
+                let (ops, records) = self.synced_records(&base)?;
+                operations.extend(ops);
+                records.data
+            }
+            None => HashMap::new(),
+        };
+        let (ops, remote) = self
+            .synced_records(&head)
+            .wrap_err_with(|| format!("cannot read the records of `{}`", url))?;
+        operations.extend(ops);
+
+        let next_id = self.data.db.next_id().max(remote.next_id());
+        let merged = sync::merge(&base, &self.data.db.data, &remote.data, next_id);
+        let changed = journal::diff(&self.data.db.data, &merged.records).len();
+        self.data.db.data = merged.records;
+        self.data.db.skip_ids_below(merged.next_id);
+        for conflict in &merged.conflicts {
+            if let sync::Conflict::Renumbered { from, to, .. } = conflict {
+                self.renumbered.push((*from, *to));
+            }
+        }
+        for row in sync::table(&merged.conflicts) {
+            eprintln!("{}", row);
+        }
+        self.pulled = Some(head);
+        match changed {
+            0 => println!("already up to date with `{}`", url),
+            n => println!("pulled {} changed records from `{}`", n, url),
+        }
+        Ok(operations)
+    }
+
+    /// commit the records on top of the remote and push them, after a pull
+    fn push(&mut self) -> Res<Vec<u32>> {
+        let mut operations = Vec::new();
+        // 只移动分支和暂存区，工作区里的文件下面会重写
+        if let Some(head) = &self.pulled {
+            operations.extend(self.sync_git(&["reset", "--quiet", head])?.0);
+        }
+        fs::write(self.sync_dir.join(sync::FILE), self.data.db.to_json()?)?;
+        operations.extend(self.sync_git(&["add", sync::FILE])?.0);
+        let (ops, status) = self.sync_git(&["status", "--porcelain"])?;
+        operations.extend(ops);
+        if status.is_empty() {
+            println!("nothing to push");
+            return Ok(operations);
+        }
+        let message = format!("fmn push from {}", self.host.name);
+        let mut commit = vec!["commit", "--quiet", "--message", &message];
+        // 没有配置 git 身份时用主机名
+        let (ops, configured) =
+            self.sync_git(&["config", "--default", "", "--get", "user.email"])?;
+        operations.extend(ops);
+        let (name, email) = (
+            format!("user.name=fmn on {}", self.host.name),
+            format!("user.email=fmn@{}", self.host.name),
+        );
+        if configured.is_empty() {
+            commit.splice(0..0, ["-c", &name, "-c", &email]);
+        }
+        operations.extend(self.sync_git(&commit)?.0);
+        operations.extend(
+            self.sync_git(&["push", "--quiet", "origin", sync::BRANCH])
+                .wrap_err("the remote may have changed meanwhile, push again")?
+                .0,
+        );
+        let (ops, commit) = self.sync_git(&["rev-parse", "HEAD"])?;
+        operations.extend(ops);
+        self.pulled = Some(commit);
+        println!("pushed {} records", self.data.db.data.len());
+        Ok(operations)
+    }
+
+    /// ask the managers of the records `ids` that have an `outdated` command for newer versions
+    ///
+    /// returns the outdated records, the managers that were asked, and the operations run
+    fn find_outdated(&self, ids: &[u32]) -> Res<(Vec<Outdated>, BTreeSet<String>, Vec<u32>)> {
+        let records: Vec<&RecordData> = ids.iter().map(|id| &self.data.db.data[id]).collect();
+        let mut jobs: Vec<Job> = Vec::new();
+        for record in &records {
+            let Some(source) = &record.source else {
+                continue;
+            };
+            if let Some(job) = jobs.iter_mut().find(|j| j.manager == *source) {
+                job.records.push(record.id);
+                continue;
+            }
+            let Some(command) = self
+                .config
+                .manager
+                .config_of(source)
+                .and_then(|c| c.outdated.as_ref())
+            else {
+                continue;
+            };
+            let mut job = self.query_job(source, command, "")?;
+            job.records.push(record.id);
+            jobs.push(job);
+        }
+        let asked = jobs.iter().map(|j| j.manager.clone()).collect();
+
+        let (operations, results) = self.run_with(jobs, None, io::sink())?;
+        let mut outdated = Vec::new();
+        for result in results {
+            let parser = self
+                .manager_config(&result.job.manager)?
+                .outdated
+                .as_ref()
+                .and_then(|c| c.parser());
+            let available: HashMap<String, FlexibleVersion> = match parser {
+                Some(parser) => parser
+                    .parse(&result.output.stdout)?
+                    .into_iter()
+                    .filter_map(|p| Some((p.name, p.latest.or(p.version)?)))
+                    .collect(),
+                None => outdated::parse(&result.output.stdout),
+            };
+            for record in &records {
+                if record.source.as_ref() != Some(&result.job.manager) {
+                    continue;
+                }
+                let Some(version) = available.get(&record.name) else {
+                    continue;
+                };
+                if record
+                    .version
+                    .as_ref()
+                    .is_some_and(|current| version.cmp_precedence(current) != Ordering::Greater)
+                {
+                    continue;
+                }
+                outdated.push(Outdated {
+                    id: record.id,
+                    name: record.name.clone(),
+                    source: result.job.manager.clone(),
+                    current: record.version.clone(),
+                    available: version.clone(),
+                    pin: record.pin.clone(),
+                });
+            }
+        }
+        outdated.sort_by_key(|o| o.id);
+        Ok((outdated, asked, operations))
+    }
+
+    /// install a pinned record at its recorded version, then hold it
+    ///
+    /// returns no jobs if that cannot be done without moving past the pin
+    fn pinned_install(&self, record: &RecordData, source: &str) -> Res<Vec<Job>> {
+        let pin = record.pin.as_ref().expect("record is pinned");
+        let config = self.manager_config(source)?;
+        let version = match &record.version {
+            Some(version) if pin.matches(version) => version.to_string(),
+            _ => {
+                eprintln!(
+                    "skipped `{}`, its recorded version is not in its pin {}",
+                    record.name, pin
+                );
+                return Ok(Vec::new());
+            }
+        };
+        let Some(install) = &config.install_version else {
+            eprintln!(
+                "skipped `{}`, it is pinned but `{}` has no install_version command",
+                record.name, source
+            );
+            return Ok(Vec::new());
+        };
+        let mut jobs = vec![self.install_job(source, install, record, Some(&version))?];
+        if let Some(hold) = &config.hold {
+            jobs.push(self.job_with(source, hold, &record.name, Some(&version))?);
+        }
+        for job in &mut jobs {
+            job.records.push(record.id);
+        }
+        Ok(jobs)
+    }
+
+    /// run the native hold or unhold command for a record, if its manager has one
+    /// and the record is of this machine
+    fn hold(
+        &self,
+        id: u32,
+        command: impl Fn(&SingleManagerConfig) -> Option<&config::Command>,
+    ) -> Res<Vec<u32>> {
+        let record = &self.data.db.data[&id];
+        let Some(source) = &record.source else {
+            return Ok(Vec::new());
+        };
+        if let Some(host) = record.host.as_ref().filter(|_| !self.is_here(record)) {
+            eprintln!(
+                "`{}` was recorded on {}, only its record was changed, hold it there",
+                record.name, host
+            );
+            return Ok(Vec::new());
+        }
+        let Some(command) = self.config.manager.config_of(source).and_then(command) else {
+            return Ok(Vec::new());
+        };
+        let version = record.version.as_ref().map(|v| v.to_string());
+        let mut job = self.job_with(source, command, &record.name, version.as_deref())?;
+        job.records.push(id);
+        self.run(vec![job], None)
+    }
+
+    /// where url sources place their binaries, `~/.local/bin` unless configured
+    fn bin_dir(&self) -> Res<PathBuf> {
+        match &self.config.bin_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(dirs::home_dir()
+                .ok_or_else(|| {
+                    eyre!("cannot find the home directory, set `bin_dir` in the config")
+                })?
+                .join(".local/bin")),
+        }
+    }
+
+    /// url and git sources, which fmn installs itself rather than through a manager
+    fn is_builtin(source: &str) -> bool {
+        source == source::URL || source == source::GIT
+    }
+
+    /// install a url or git source, `latest` builds the git reference rather than the recorded commit
+    ///
+    /// records the checksum of the file placed, so `fmn verify` notices if it changes
+    fn install_builtin(&self, record: &mut RecordData, id: u32, latest: bool) -> Res<Vec<u32>> {
+        let operations = match record.source.as_deref() {
+            Some(source::URL) => self.install_url(record, id)?,
+            _ => self.install_git(record, id, latest)?,
+        };
+        record.checksum = match &record.location {
+            Some(location) if location.is_file() => Some(download::sha256(location)?),
+            _ => None,
+        };
+        Ok(operations)
+    }
+
+    /// where a package installed by `source` is, from its `files` command or else PATH
+    ///
+    /// also returns the operations run, a failing `files` command is not an error
+    fn locate(&self, source: &str, name: &str, id: u32) -> (Option<PathBuf>, Vec<u32>) {
+        let mut operations = Vec::new();
+        if let Some(command) = self
+            .config
+            .manager
+            .config_of(source)
+            .and_then(|c| c.files.as_ref())
+            && let Ok(mut job) = self.query_job(source, command, name)
+        {
+            job.records.push(id);
+            if let Ok((ops, results)) = self.run_with(vec![job], None, io::sink()) {
+                operations = ops;
+                if let Some(binary) = verify::binary_in(&results[0].output.stdout, name) {
+                    return (Some(binary), operations);
+                }
+            }
+        }
+        (which::which(name).ok(), operations)
+    }
+
+    /// clone, check out and build a git source, records the commit hash as its version
+    fn install_git(&self, record: &mut RecordData, id: u32, latest: bool) -> Res<Vec<u32>> {
+        let Some(Source::Git { url, reference }) = Source::of(record) else {
+            bail!("`{}` has no git url in its origin", record.name);
+        };
+        let reference = match (&record.version, latest) {
+            (Some(commit), false) => Some(commit.to_string()),
+            _ => reference,
+        };
+        let prefix = self.git_prefix()?;
+        fs::create_dir_all(&prefix)?;
+
+        let work = std::env::temp_dir().join(format!("fmn-git-{}-{}", process::id(), id));
+        let _ = fs::remove_dir_all(&work);
+        let checkout = work.join(&record.name);
+        let steps = vec![
+            git::clone_job(&url, &checkout)?,
+            git::checkout_job(&checkout, reference.as_deref())?,
+            git::rev_parse_job(&checkout)?,
+        ];
+        let build = git::build_job(&self.config.git.build, &checkout, &prefix, &record.name);
+        let result = self.build(record, id, steps, build);
+        let _ = fs::remove_dir_all(&work);
+        let operations = result?;
+        // 构建命令不一定装到 bin 下面，PATH 里同名的不一定是它，不能记
+        record.location = Some(prefix.join("bin").join(&record.name)).filter(|b| b.is_file());
+        if record.location.is_none() {
+            eprintln!(
+                "`{}` was built but is not at {}, its files are not tracked",
+                record.name,
+                prefix.join("bin").join(&record.name).display()
+            );
+        }
+        Ok(operations)
     }
 
-    pub fn from_vec(data: Vec<RecordData>) -> Self {
-        let mut index = HashMap::new();
-        for rec in data {
-            index.insert(rec.id, rec);
+    /// where git sources are installed, `~/.local` unless configured
+    fn git_prefix(&self) -> Res<PathBuf> {
+        match &self.config.git.prefix {
+            Some(prefix) => Ok(prefix.clone()),
+            None => Ok(dirs::home_dir()
+                .ok_or_else(|| {
+                    eyre!("cannot find the home directory, set `git.prefix` in the config")
+                })?
+                .join(".local")),
         }
-        Self { index }
+    }
+
+    /// whether fmn placed `location` of a url or git record itself, only those are deleted
+    fn placed(&self, record: &RecordData, location: &Path) -> Res<bool> {
+        let dir = match record.source.as_deref() {
+            Some(source::URL) => self.bin_dir()?,
+            _ => self.git_prefix()?.join("bin"),
+        };
+        Ok(location.parent() == Some(dir.as_path()))
+    }
+
+    /// run the quiet checkout `steps` one after another, the last one prints the commit,
+    /// then `build` with its output shown
+    fn build(
+        &self,
+        record: &mut RecordData,
+        id: u32,
+        steps: Vec<Job>,
+        mut build: Job,
+    ) -> Res<Vec<u32>> {
+        let mut operations = Vec::new();
+        let mut commit = String::new();
+        // 依次执行，后一步依赖前一步
+        for mut job in steps {
+            job.records.push(id);
+            let (ops, results) = self.run_with(vec![job], None, io::sink())?;
+            operations.extend(ops);
+            commit = results[0].output.stdout.trim().to_string();
+        }
+        build.records.push(id);
+        operations.extend(self.run(vec![build], None)?);
+        record.version = Some(FlexibleVersion::parse(&commit));
+        Ok(operations)
+    }
+
+    /// download, verify and place the binary of a url source
+    ///
+    /// sets the location of the record, and its sha256 if it had none
+    fn install_url(&self, record: &mut RecordData, id: u32) -> Res<Vec<u32>> {
+        let work = std::env::temp_dir().join(format!("fmn-url-{}-{}", process::id(), id));
+        let _ = fs::remove_dir_all(&work);
+        fs::create_dir_all(&work)?;
+        let result = self.fetch(record, id, &work);
+        let _ = fs::remove_dir_all(&work);
+        result
+    }
+
+    fn fetch(&self, record: &mut RecordData, id: u32, work: &Path) -> Res<Vec<u32>> {
+        let url = record
+            .origin
+            .clone()
+            .ok_or_else(|| eyre!("`{}` has no url in its origin", record.name))?;
+        let file_name = Source::Url(url.clone()).file_name().to_string();
+        let file = work.join(&file_name);
+        let mut job = download::fetch_job(&url, &file)?;
+        job.records.push(id);
+        let mut operations = self.run(vec![job], None)?;
+        match &record.sha256 {
+            Some(expected) => download::verify(&file, expected)?,
+            None => record.sha256 = Some(download::sha256(&file)?),
+        }
+
+        let unpacked = work.join("unpacked");
+        fs::create_dir_all(&unpacked)?;
+        let (binary, name) = match download::extract_job(&file, &unpacked)? {
+            Some(mut job) => {
+                job.records.push(id);
+                operations.extend(self.run(vec![job], None)?);
+                let binary = download::find_binary(&unpacked, &record.name)?;
+                let name = binary
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                (binary, name)
+            }
+            None => (file, record.name.clone()),
+        };
+        record.location = Some(download::place(&binary, &self.bin_dir()?, &name)?);
+        Ok(operations)
+    }
+
+    fn manager_config(&self, source: &str) -> Res<&SingleManagerConfig> {
+        self.config
+            .manager
+            .config_of(source)
+            .ok_or_else(|| eyre!("no config for package manager `{}`", source))
+    }
+
+    /// build the job running `command` of `source` for `package_name`,
+    /// with the privilege policy of the manager applied
+    fn job(
+        &self,
+        source: &str,
+        command: impl Fn(&SingleManagerConfig) -> &config::Command,
+        package_name: &str,
+    ) -> Res<Job> {
+        let config = self.manager_config(source)?;
+        self.job_with(source, command(config), package_name, None)
+    }
+
+    fn job_with(
+        &self,
+        source: &str,
+        command: &config::Command,
+        package_name: &str,
+        version: Option<&str>,
+    ) -> Res<Job> {
+        self.build_job(source, command, package_name, version, None, true)
+    }
+
+    /// build the job installing `record` with `command`, from the remote of its source if any
+    ///
+    /// fails if the record has a remote but `command` has no `{remote}` to put it in
+    fn install_job(
+        &self,
+        source: &str,
+        command: &config::Command,
+        record: &RecordData,
+        version: Option<&str>,
+    ) -> Res<Job> {
+        let remote = record.origin.as_deref();
+        if let Some(remote) = remote {
+            ensure!(
+                command.takes_remote(),
+                "`{}` cannot install `{}` from the remote `{}`, its install command has no {{remote}}",
+                source,
+                record.name,
+                remote
+            );
+        }
+        self.build_job(source, command, &record.name, version, remote, true)
+    }
+
+    /// build the job for a command that only reads, it is not run as root
+    /// unless the command asks for it itself
+    fn query_job(&self, source: &str, command: &config::Command, package_name: &str) -> Res<Job> {
+        self.build_job(source, command, package_name, None, None, false)
+    }
+
+    fn build_job(
+        &self,
+        source: &str,
+        command: &config::Command,
+        package_name: &str,
+        version: Option<&str>,
+        remote: Option<&str>,
+        changes: bool,
+    ) -> Res<Job> {
+        let config = self.manager_config(source)?;
+        let argv = command.argv(source, package_name, version, remote);
+        // 提前报错，而不是等到启动进程时才失败
+        if which::which(&argv[0]).is_err() {
+            bail!(
+                "`{}` is not available on this host, `{}` was not found in PATH, see `fmn managers`",
+                source,
+                argv[0]
+            );
+        }
+        let argv = config.privilege_of(command, changes).wrap(
+            source,
+            argv,
+            config.escalate,
+            privilege::is_root(),
+        )?;
+        let mut job = Job::new(source, argv);
+        job.timeout = config.timeout.map(Duration::from_secs);
+        job.retries = config.retries;
+        if let Some(delay) = config.retry_delay {
+            job.retry_delay = Duration::from_secs(delay);
+        }
+        Ok(job)
+    }
+
+    /// ask for the password once up front, instead of letting parallel jobs prompt at the same time
+    fn authenticate(jobs: &[Job]) -> Res<()> {
+        let escalations: BTreeSet<Escalation> = jobs
+            .iter()
+            .filter_map(|j| Escalation::of(&j.argv))
+            .collect();
+        for escalation in escalations {
+            escalation.authenticate()?;
+        }
+        Ok(())
+    }
+
+    /// run jobs through the scheduler and log them, fails if any of them failed
+    ///
+    /// returns the ids of the log entries
+    fn run(&self, jobs: Vec<Job>, limit: Option<usize>) -> Res<Vec<u32>> {
+        Ok(self.run_with(jobs, limit, io::stdout())?.0)
+    }
+
+    /// [`Manager::run`] with the output of the jobs forwarded to `out`,
+    /// also returns the results
+    fn run_with<W: Write + Send>(
+        &self,
+        jobs: Vec<Job>,
+        limit: Option<usize>,
+        out: W,
+    ) -> Res<(Vec<u32>, Vec<JobResult>)> {
+        Self::authenticate(&jobs)?;
+        let scheduler = match limit.or(self.config.jobs) {
+            Some(n) => Scheduler::new(n),
+            None => Scheduler::default(),
+        };
+        let results = scheduler.run(jobs, out);
+        // Ctrl-C 打断的任务不记进日志
+        let operations = results
+            .iter()
+            .filter(|r| r.success() || !exec::interrupted())
+            .map(|r| self.log.append(r))
+            .collect::<Res<Vec<u32>>>()?;
+        let failed: Vec<&JobResult> = results.iter().filter(|r| !r.success()).collect();
+        if failed.is_empty() {
+            return Ok((operations, results));
+        }
+        for r in &failed {
+            match &r.status {
+                Ok(Some(code)) => eprintln!("`{}` exited with {}", r.job.argv.join(" "), code),
+                Ok(None) => eprintln!("`{}` was terminated", r.job.argv.join(" ")),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        bail!("{} command(s) failed", failed.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{profile, source::Source};
+    use std::path::PathBuf;
+
+    fn manager(name: &str) -> (Manager, PathBuf) {
+        manager_with(name, Config::default())
+    }
+
+    fn manager_with(name: &str, config: Config) -> (Manager, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("fmn-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let data = DataManager::new(&dir.join("records.json")).unwrap();
+        let manager = Manager::new(
+            config,
+            data,
+            OpLog::new(&dir),
+            Journal::new(&dir),
+            &dir.join("sync"),
+        );
+        (manager, dir)
+    }
+
+    /// an empty directory for the files a test installs or serves
+    fn fixtures(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fmn-fixtures-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// `fmn apply` of every record, without a manifest
+    fn apply_all() -> Command {
+        Command::Apply {
+            query: Query::default(),
+            jobs: None,
+            manifest: None,
+            prune: false,
+        }
+    }
+
+    fn record(name: &str) -> Command {
+        Command::Record(RecordData {
+            name: name.into(),
+            ..Default::default()
+        })
+    }
+
+    fn names(manager: &Manager) -> Vec<&str> {
+        manager.records().iter().map(|r| r.name.as_str()).collect()
+    }
+
+    /// a config with a `sh` manager whose install, upgrade and remove do nothing,
+    /// `extra` is added to its table
+    fn sh_config(extra: &str) -> Config {
+        format!(
+            r#"
+[manager.sh]
+install = {{ template = "-c true" }}
+upgrade = {{ template = "-c true" }}
+remove = {{ template = "-c true" }}
+{}
+"#,
+            extra
+        )
+        .parse()
+        .unwrap()
+    }
+
+    /// the commands the manager ran, oldest first
+    fn argvs(manager: &Manager) -> Vec<String> {
+        manager
+            .log()
+            .entries()
+            .unwrap()
+            .iter()
+            .map(|o| o.argv.join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn test_unavailable_manager() -> Res<()> {
+        let config: Config = r#"
+[manager.fmn-missing-manager]
+install = "install"
+upgrade = "upgrade"
+remove = "remove"
+"#
+        .parse()?;
+        let (mut manager, dir) = manager_with("unavailable", config);
+        let err = manager
+            .execute(Command::Install(RecordData {
+                name: "ripgrep".into(),
+                source: Some("fmn-missing-manager".into()),
+                ..Default::default()
+            }))
+            .unwrap_err();
+        assert!(err.to_string().contains("not available on this host"));
+        assert!(manager.records().is_empty());
+        assert!(manager.log().entries()?.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_install_source() -> Res<()> {
+        let config: Config = r#"
+[manager.sh]
+install = { template = "-c true {remote}" }
+upgrade = { template = "-c true" }
+remove = { template = "-c true" }
+install_version = { program = "true", template = "install {package_name}@{version}" }
+list = { program = "echo", template = "tool 1.2.5" }
+"#
+        .parse()?;
+        let (mut manager, dir) = manager_with("install-source", config);
+        for source in ["sh:tool@1.2", "sh:remote/other", "sh:plain"] {
+            let source: Source = source.parse()?;
+            manager.execute(Command::Install(source.record()))?;
+        }
+        let installs = |m: &Manager| -> Vec<String> {
+            argvs(m)
+                .into_iter()
+                .filter(|a| !a.starts_with("echo"))
+                .collect()
+        };
+        assert_eq!(
+            vec!["true install tool@1.2", "sh -c true remote", "sh -c true"],
+            installs(&manager)
+        );
+        let records = manager.records();
+        // `@1.2` 是 pin，版本是管理器列出来的
+        assert_eq!(
+            Some("=1.2".to_string()),
+            records[0].pin.as_ref().map(|p| p.to_string())
+        );
+        assert_eq!(
+            Some("1.2.5".to_string()),
+            records[0].version.as_ref().map(|v| v.to_string())
+        );
+        assert_eq!(None, records[2].pin);
+        assert_eq!(Some("remote".to_string()), records[1].origin);
+
+        // 再装一次是同一条记录
+        manager.execute(Command::Install("sh:tool@1.2".parse::<Source>()?.record()))?;
+        assert_eq!(vec!["tool", "other", "plain"], names(&manager));
+        assert_eq!(
+            Some("true install tool@1.2".to_string()),
+            installs(&manager).pop()
+        );
+
+        let err = manager
+            .execute(Command::Install(
+                "pacman:ripgrep@14".parse::<Source>()?.record(),
+            ))
+            .unwrap_err();
+        assert!(err.to_string().contains("install_version"), "{}", err);
+        // install_version 没有 {remote}，不能默默从默认的仓库装
+        let err = manager
+            .execute(Command::Install(
+                "sh:remote/tool@1.2".parse::<Source>()?.record(),
+            ))
+            .unwrap_err();
+        assert!(err.to_string().contains("{remote}"), "{}", err);
+        let _ = fs::remove_dir_all(&dir);
+        Ok(())
+    }
+
+    /// serve `files` over HTTP on a local port, until the test ends
+    fn serve(files: Vec<(&'static str, Vec<u8>)>) -> String {
+        use std::{
+            io::{BufRead, BufReader},
+            net::TcpListener,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut request = String::new();
+                let mut reader = BufReader::new(&stream);
+                reader.read_line(&mut request).unwrap();
+                // 读完请求头
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    line.clear();
+                }
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let response = match files.iter().find(|(p, _)| *p == path) {
+                    Some((_, body)) => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend(body);
+                        response
+                    }
+                    None => {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn test_url_source() -> Res<()> {
+        let fixtures = fixtures("url");
+        fs::create_dir_all(fixtures.join("tool-1.0/doc"))?;
+        fs::write(fixtures.join("tool-1.0/doc/README"), "read me")?;
+        let binary = download::place(
+            &fixtures.join("tool-1.0/doc/README"),
+            &fixtures.join("tool-1.0"),
+            "tool",
+        )?;
+        fs::write(&binary, "#!/bin/sh\necho tool\n")?;
+        let status = process::Command::new("tar")
+            .arg("-czf")
+            .arg(fixtures.join("tool-1.0.tar.gz"))
+            .arg("-C")
+            .arg(&fixtures)
+            .arg("tool-1.0")
+            .status()?;
+        assert!(status.success());
+        let tarball = fs::read(fixtures.join("tool-1.0.tar.gz"))?;
+        let server = serve(vec![
+            ("/releases/tool-1.0.tar.gz", tarball),
+            ("/jq-linux-amd64", b"#!/bin/sh\necho jq\n".to_vec()),
+        ]);
+
+        let bin_dir = fixtures.join("bin");
+        let config: Config = format!("bin_dir = {:?}", bin_dir.to_string_lossy()).parse()?;
+        let (mut manager, dir) = manager_with("url", config);
+        let source: Source = format!("{}/releases/tool-1.0.tar.gz", server).parse()?;
+        manager.execute(Command::Install(source.record()))?;
+        let record = manager.records()[0].clone();
+        assert_eq!("tool-1.0", record.name);
+        assert_eq!(Some(bin_dir.join("tool")), record.location);
+        assert_eq!(
+            Some(download::sha256(&fixtures.join("tool-1.0.tar.gz"))?),
+            record.sha256
+        );
+        assert_eq!(
+            "#!/bin/sh\necho tool\n",
+            fs::read_to_string(bin_dir.join("tool"))?
+        );
+
+        let mut jq = format!("{}/jq-linux-amd64", server)
+            .parse::<Source>()?
+            .record();
+        jq.sha256 = Some("0".repeat(64));
+        let err = manager.execute(Command::Install(jq.clone())).unwrap_err();
+        assert!(err.to_string().contains("sha256 mismatch"), "{}", err);
+        jq.sha256 = None;
+        manager.execute(Command::Install(jq))?;
+        assert!(bin_dir.join("jq-linux-amd64").is_file());
+
+        let missing = format!("{}/missing.zip", server)
+            .parse::<Source>()?
+            .record();
+        assert!(manager.execute(Command::Install(missing)).is_err());
+        assert_eq!(2, manager.records().len());
+
+        // apply 会把删掉的文件重新下载回来
+        fs::remove_file(bin_dir.join("tool"))?;
+        manager.execute(apply_all())?;
+        assert!(bin_dir.join("tool").is_file());
+
+        manager.execute(Command::Remove {
+            id: 0,
+            record_only: false,
+        })?;
+        assert!(!bin_dir.join("tool").exists());
+        assert!(bin_dir.join("jq-linux-amd64").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::remove_dir_all(&fixtures)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_git_source() -> Res<()> {
+        let fixtures = fixtures("git");
+        let work = fixtures.join("work");
+        fs::create_dir_all(&work)?;
+        let git = |args: &[&str]| -> Res<String> {
+            let output = process::Command::new("git")
+                .args(["-c", "user.name=fmn", "-c", "user.email=fmn@example.com"])
+                .arg("-C")
+                .arg(&work)
+                .args(args)
+                .output()?;
+            ensure!(output.status.success(), "git {:?} failed", args);
+            Ok(String::from_utf8(output.stdout)?.trim().to_string())
+        };
+        git(&["init", "-q", "-b", "main"])?;
+        for version in ["v1", "v2"] {
+            fs::write(work.join("tool"), version)?;
+            git(&["add", "tool"])?;
+            git(&["commit", "-q", "-m", version])?;
+            git(&["tag", version])?;
+        }
+        let v1 = git(&["rev-parse", "v1^{commit}"])?;
+        let v2 = git(&["rev-parse", "v2^{commit}"])?;
+        git(&["clone", "-q", "--bare", ".", "../tool.git"])?;
+
+        let prefix = fixtures.join("prefix");
+        let config: Config = format!(
+            "[git]\nbuild = \"cp {{checkout_dir}}/tool {{prefix}}/bin/{{package_name}}\"\nprefix = {:?}",
+            prefix.to_string_lossy()
+        )
+        .parse()?;
+        fs::create_dir_all(prefix.join("bin"))?;
+        let (mut manager, dir) = manager_with("git", config);
+        let url = format!("file://{}", fixtures.join("tool.git").display());
+        let source: Source = format!("git+{}#v1", url).parse()?;
+        manager.execute(Command::Install(source.record()))?;
+        let record = manager.records()[0].clone();
+        assert_eq!("tool", record.name);
+        assert_eq!(Some(FlexibleVersion::parse(&v1)), record.version);
+        assert_eq!(Some(prefix.join("bin/tool")), record.location);
+        assert_eq!(
+            Some(download::sha256(&prefix.join("bin/tool"))?),
+            record.checksum
+        );
+
+        // apply 按记录下来的提交构建，而不是 tag 现在指向的
+        let mut record = manager.records()[0].clone();
+        record.set("origin", &url)?;
+        manager.execute(Command::Update(record))?;
+        fs::remove_file(prefix.join("bin/tool"))?;
+        manager.execute(apply_all())?;
+        assert_eq!("v1", fs::read_to_string(prefix.join("bin/tool"))?);
+
+        // upgrade 跟随引用，没有引用时是默认分支
+        manager.execute(Command::Upgrade {
+            query: Query {
+                all: true,
+                ..Default::default()
+            },
+            jobs: None,
+        })?;
+        assert_eq!("v2", fs::read_to_string(prefix.join("bin/tool"))?);
+        assert_eq!(
+            Some(FlexibleVersion::parse(&v2)),
+            manager.records()[0].version
+        );
+
+        let missing: Source = format!("git+{}#v3", url).parse()?;
+        let mut record = missing.record();
+        record.name = "other".into();
+        assert!(manager.execute(Command::Install(record)).is_err());
+        assert_eq!(1, manager.records().len());
+
+        // 不在 prefix 下的文件不是 fmn 放的，不删
+        let mut record = manager.records()[0].clone();
+        record.location = Some(work.join("tool"));
+        manager.execute(Command::Update(record))?;
+        manager.execute(Command::Remove {
+            id: 0,
+            record_only: false,
+        })?;
+        assert!(work.join("tool").is_file());
+        assert!(manager.records().is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::remove_dir_all(&fixtures)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_locate_and_verify() -> Res<()> {
+        let fixtures = fixtures("locate");
+        fs::write(fixtures.join("script"), "#!/bin/sh\n")?;
+        let tool = download::place(&fixtures.join("script"), &fixtures.join("bin"), "tool")?;
+        let config = sh_config(&format!(
+            r#"files = {{ program = "echo", template = "{}/bin/{{package_name}}" }}"#,
+            fixtures.display()
+        ));
+        let (mut manager, dir) = manager_with("locate", config);
+        manager.execute(Command::Install("sh:tool".parse::<Source>()?.record()))?;
+        // files 里没有，就在 PATH 里找
+        manager.execute(Command::Install("sh:sh".parse::<Source>()?.record()))?;
+        manager.execute(record("recorded"))?;
+        let locations: Vec<Option<PathBuf>> = manager
+            .records()
+            .iter()
+            .map(|r| r.location.clone())
+            .collect();
+        assert_eq!(
+            vec![Some(tool.clone()), which::which("sh").ok(), None],
+            locations
+        );
+
+        let mut record = manager.records()[0].clone();
+        record.location = None;
+        manager.execute(Command::Update(record))?;
+        manager.execute(apply_all())?;
+        assert_eq!(Some(tool.clone()), manager.records()[0].location);
+
+        fs::remove_file(&tool)?;
+        let statuses: Vec<verify::Status> = manager
+            .verify(&Query::default())?
+            .into_iter()
+            .map(|v| v.status)
+            .collect();
+        assert_eq!(
+            vec![
+                verify::Status::Missing,
+                verify::Status::Ok,
+                verify::Status::Unknown
+            ],
+            statuses
+        );
+        let _ = fs::remove_dir_all(&dir);
+        fs::remove_dir_all(&fixtures)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_manifest() -> Res<()> {
+        let config = sh_config(
+            r#"
+install_version = { program = "true", template = "{package_name}={version}" }
+list = { program = "echo", template = "tool 1.5.0" }
+hold = { program = "true", template = "hold {package_name}" }
+unhold = { program = "true", template = "unhold {package_name}" }"#,
+        );
+        let (mut manager, dir) = manager_with("manifest", config);
+        for (name, tags) in [("kept", vec!["mine".to_string()]), ("old", vec![])] {
+            manager.execute(Command::Record(RecordData {
+                name: name.into(),
+                source: Some("sh".into()),
+                tags,
+                ..Default::default()
+            }))?;
+        }
+        manager.execute(record("note"))?;
+        let manifest: Manifest = r#"
+[sh]
+tool = "^1"
+exact = "2.0"
+kept = { version = "^1", tags = ["dev"] }
+other = { hosts = ["fmn-no-such-host-*"] }
+"#
+        .parse()?;
+        let apply = |manifest: &Manifest, prune| Command::Apply {
+            query: Query::default(),
+            jobs: None,
+            manifest: Some(manifest.clone()),
+            prune,
+        };
+        manager.execute(apply(&manifest, false))?;
+        assert_eq!(
+            vec!["kept", "old", "note", "exact", "tool"],
+            names(&manager)
+        );
+        let kept = manager.resolve("kept")?;
+        assert_eq!(vec!["mine", "dev"], kept.tags);
+        assert!(kept.installation_date.is_none());
+        // 列表里报告的版本
+        let tool = manager.resolve("tool")?;
+        assert_eq!(Some(FlexibleVersion::parse("1.5.0")), tool.version);
+        assert_eq!(
+            Some("^1".to_string()),
+            tool.pin.as_ref().map(|p| p.to_string())
+        );
+        assert!(tool.installation_date.is_some());
+        let exact = manager.resolve("exact")?;
+        assert_eq!(Some(FlexibleVersion::parse("2.0")), exact.version);
+        let ran = argvs(&manager);
+        assert!(ran.contains(&"true exact=2.0".to_string()), "{:?}", ran);
+        // 范围内的版本按列表里的装
+        assert!(ran.contains(&"true tool=1.5.0".to_string()), "{:?}", ran);
+        // 和 fmn pin 一样在管理器里 hold
+        assert!(ran.contains(&"true hold kept".to_string()), "{:?}", ran);
+
+        // 没有来源的记录留着
+        let manifest: Manifest = "[sh]\ntool = \"^1\"\nexact = \"2.0\"\nkept = {}".parse()?;
+        manager.execute(apply(&manifest, true))?;
+        assert_eq!(vec!["kept", "note", "exact", "tool"], names(&manager));
+        assert_eq!(None, manager.resolve("kept")?.pin);
+        assert!(argvs(&manager).contains(&"true unhold kept".to_string()));
+
+        let manifest: Manifest = "[sh]\nmissing = \"^3\"".parse()?;
+        let err = manager.execute(apply(&manifest, false)).unwrap_err();
+        assert!(err.to_string().contains("no version"), "{}", err);
+        assert!(manager.resolve("missing").is_err());
+        assert_eq!(
+            "apply manifest --prune",
+            manager.journal().last_undoable()?.unwrap().command
+        );
+        let _ = fs::remove_dir_all(&dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_host() -> Res<()> {
+        let mut config = sh_config(
+            r#"
+hold = { program = "true", template = "hold {package_name}" }
+unhold = { program = "true", template = "unhold {package_name}" }"#,
+        );
+        config.host = Some("fmn-test-host".into());
+        let (mut manager, dir) = manager_with("host", config);
+        manager.execute(Command::Record(RecordData {
+            name: "here".into(),
+            location: Some("/fmn/no/such/file".into()),
+            ..Default::default()
+        }))?;
+        let mut elsewhere = Host::current(Some("server"));
+        elsewhere.machine_id = Some("fmn-other-machine".into());
+        manager.execute(Command::Record(RecordData {
+            name: "elsewhere".into(),
+            location: Some("/fmn/no/such/file".into()),
+            host: Some(elsewhere),
+            ..Default::default()
+        }))?;
+        let here = manager.resolve("here")?;
+        assert_eq!("fmn-test-host", here.host.as_ref().unwrap().name);
+        assert!(manager.is_here(here));
+        assert!(!manager.is_here(manager.resolve("elsewhere")?));
+
+        let verified: Vec<String> = manager
+            .verify(&Query::default())?
+            .into_iter()
+            .map(|v| v.name)
+            .collect();
+        assert_eq!(vec!["here"], verified);
+
+        // 别的机器上的记录不装、不清掉，清单给这台机器另加一条
+        let mut record = manager.resolve("elsewhere")?.clone();
+        record.source = Some("sh".into());
+        manager.execute(Command::Update(record))?;
+        let manifest: Manifest = "[sh]\nelsewhere = {}".parse()?;
+        manager.execute(Command::Apply {
+            query: Query::default(),
+            jobs: None,
+            manifest: Some(manifest),
+            prune: true,
+        })?;
+        assert_eq!(vec!["here", "elsewhere", "elsewhere"], names(&manager));
+        assert_eq!(vec!["sh -c true"], argvs(&manager));
+        let ours = manager.records()[2];
+        assert!(manager.is_here(ours));
+        assert!(!manager.is_here(manager.records()[1]));
+
+        // 别的机器上的包不在这里 hold，记录照样 pin
+        manager.execute(Command::Pin {
+            id: 1,
+            range: Some("^1".parse()?),
+        })?;
+        manager.execute(Command::Unpin { id: 1 })?;
+        assert_eq!(vec!["sh -c true"], argvs(&manager));
+
+        let err = manager
+            .execute(Command::Remove {
+                id: 1,
+                record_only: false,
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("--record-only"), "{}", err);
+        manager.execute(Command::Remove {
+            id: 1,
+            record_only: true,
+        })?;
+        assert_eq!(vec!["here", "elsewhere"], names(&manager));
+
+        // 反向撤销也不去装别的机器上的包
+        let mut tx = manager.journal.last_undoable()?.unwrap();
+        tx.operations.push(0);
+        manager.undo(&tx, true)?;
+        assert_eq!(vec!["here", "elsewhere", "elsewhere"], names(&manager));
+        assert_eq!(vec!["sh -c true"], argvs(&manager));
+        let _ = fs::remove_dir_all(&dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync() -> Res<()> {
+        let remote = std::env::temp_dir().join(format!("fmn-sync-remote-{}.git", process::id()));
+        let _ = fs::remove_dir_all(&remote);
+        let status = process::Command::new("git")
+            .args(["init", "--quiet", "--bare"])
+            .arg(&remote)
+            .status()?;
+        ensure!(status.success(), "git init failed");
+        let url = remote.to_string_lossy().into_owned();
+        let (mut a, dir_a) = manager_with("sync-a", Config::default());
+        let (mut b, dir_b) = manager_with("sync-b", Config::default());
+        assert_eq!(None, a.remote()?);
+        assert!(a.execute(Command::Pull).is_err());
+        a.add_remote(&url)?;
+        b.add_remote(&url)?;
+        assert_eq!(Some(url.clone()), a.remote()?);
+        b.execute(Command::Pull)?;
+
+        a.execute(record("a1"))?;
+        a.execute(record("shared"))?;
+        a.execute(Command::Push)?;
+        // 两边都用了 id 0，b 的记录换一个 id
+        b.execute(record("b1"))?;
+        b.execute(Command::Push)?;
+        assert_eq!(vec!["a1", "shared", "b1"], names(&b));
+        assert_eq!(2, b.resolve("b1")?.id);
+        // b1 的历史跟着换了 id，a1 是同步加进来的
+        let history: Vec<(u32, String)> = b
+            .journal
+            .history("b1")?
+            .iter()
+            .map(|e| (e.record, e.event.to_string()))
+            .collect();
+        assert_eq!(vec![(2, "created".to_string())], history);
+        let history = b.journal.history("a1")?;
+        assert_eq!("push", history[0].command);
+        assert_eq!(0, history[0].record);
+
+        a.execute(Command::Tag(TagCommand::Add {
+            tag: "x".into(),
+            query: Query {
+                records: vec!["shared".into()],
+                ..Default::default()
+            },
+        }))?;
+        a.execute(Command::Push)?;
+        assert_eq!(vec!["a1", "shared", "b1"], names(&a));
+
+        // a 加了标签，b 改了描述，合并后两个都在
+        let mut shared = b.resolve("shared")?.clone();
+        shared.description = Some("from b".into());
+        b.execute(Command::Update(shared))?;
+        b.execute(Command::Remove {
+            id: 0,
+            record_only: true,
+        })?;
+        b.execute(Command::Push)?;
+        let shared = b.resolve("shared")?;
+        assert_eq!(Some("from b".to_string()), shared.description);
+        assert_eq!(vec!["x"], shared.tags);
+
+        a.execute(Command::Pull)?;
+        assert_eq!(vec!["shared", "b1"], names(&a));
+        assert_eq!(Some("from b".to_string()), a.resolve("shared")?.description);
+        assert_eq!(vec!["x"], a.resolve("shared")?.tags);
+        let text = fs::read_to_string(dir_b.join("sync").join(sync::FILE))?;
+        assert!(text.contains("\n      \"name\": \"b1\",\n"), "{}", text);
+
+        a.execute(Command::Undo { inverse: false })?;
+        assert_eq!(vec!["a1", "shared", "b1"], names(&a));
+
+        // 推送失败后，下次合并的基准还是远程上的，本地加的记录不会丢
+        let hook = dir_a.join("reject");
+        fs::write(&hook, "#!/bin/sh\nexit 1\n")?;
+        download::place(&hook, &remote.join("hooks"), "pre-receive")?;
+        a.execute(record("a2"))?;
+        assert!(a.execute(Command::Push).is_err());
+        fs::remove_file(remote.join("hooks/pre-receive"))?;
+        b.execute(record("b2"))?;
+        b.execute(Command::Push)?;
+        a.execute(Command::Pull)?;
+        assert!(names(&a).contains(&"a2"), "{:?}", names(&a));
+        assert!(names(&a).contains(&"b2"), "{:?}", names(&a));
+        for dir in [dir_a, dir_b, remote] {
+            let _ = fs::remove_dir_all(dir);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_profile() -> Res<()> {
+        let config: Config = r#"
+[manager.sh]
+install = { program = "true", template = "install {package_name}" }
+upgrade = { template = "-c true" }
+remove = { template = "-c true" }
+
+[profile.base]
+records = ["htop"]
+
+[profile.work]
+include = ["base"]
+tags = ["dev"]
+"#
+        .parse()?;
+        let (mut manager, dir) = manager_with("profile", config);
+        for (name, tags) in [("htop", vec![]), ("rg", vec!["dev"]), ("steam", vec![])] {
+            manager.execute(Command::Record(RecordData {
+                name: name.into(),
+                source: Some("sh".into()),
+                tags: tags.into_iter().map(String::from).collect(),
+                ..Default::default()
+            }))?;
+        }
+        let query = Query {
+            profile: Some(profile::members(
+                &manager.config().profile,
+                &["work".into()],
+            )?),
+            ..Default::default()
+        };
+        manager.execute(Command::Apply {
+            query,
+            jobs: None,
+            manifest: None,
+            prune: false,
+        })?;
+        let mut ran = argvs(&manager);
+        ran.sort();
+        assert_eq!(vec!["true install htop", "true install rg"], ran);
+        let _ = fs::remove_dir_all(&dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock() -> Res<()> {
+        let fixtures = fixtures("lock");
+        let server = serve(vec![("/jq", b"#!/bin/sh\necho jq\n".to_vec())]);
+        let mut config = sh_config(
+            r#"install_version = { program = "true", template = "install {package_name}@{version}" }"#,
+        );
+        config.bin_dir = Some(fixtures.join("bin"));
+        let (mut manager, dir) = manager_with("lock", config.clone());
+        manager.execute(Command::Install("sh:tool@1.2".parse::<Source>()?.record()))?;
+        manager.execute(Command::Install(
+            format!("{}/jq", server).parse::<Source>()?.record(),
+        ))?;
+        manager.execute(Command::Record(RecordData {
+            name: "unknown".into(),
+            source: Some("sh".into()),
+            ..Default::default()
+        }))?;
+        let err = manager.lock().unwrap_err();
+        assert!(err.to_string().contains("unknown"), "{}", err);
+        manager.execute(Command::Remove {
+            id: 2,
+            record_only: true,
+        })?;
+        let lockfile = manager.lock()?;
+        assert_eq!(2, lockfile.packages.len());
+        assert_eq!(Some("1.2".to_string()), lockfile.packages[1].version);
+        assert!(lockfile.packages[0].sha256.is_some() && lockfile.packages[0].checksum.is_some());
+
+        // 在另一台干净的机器上按锁文件安装
+        fs::remove_dir_all(fixtures.join("bin"))?;
+        let (mut other, other_dir) = manager_with("lock-other", config);
+        other.execute(Command::ApplyLocked {
+            lockfile: lockfile.clone(),
+            jobs: None,
+        })?;
+        let ran = argvs(&other);
+        assert!(
+            ran.contains(&"true install tool@1.2".to_string()),
+            "{:?}",
+            ran
+        );
+        assert_eq!(vec!["jq", "tool"], names(&other));
+        assert_eq!(
+            Some(FlexibleVersion::parse("1.2")),
+            other.resolve("tool")?.version
+        );
+        assert!(fixtures.join("bin/jq").is_file());
+
+        let mut tampered = lockfile.clone();
+        tampered.packages[0].sha256 = Some("0".repeat(64));
+        tampered.packages[0].name = "jq-again".into();
+        let installs = |m: &Manager| argvs(m).iter().filter(|a| a.starts_with("true")).count();
+        let ran = installs(&other);
+        assert!(
+            other
+                .execute(Command::ApplyLocked {
+                    lockfile: tampered,
+                    jobs: None,
+                })
+                .is_err()
+        );
+        // 下载对不上时管理器一个都没跑
+        assert_eq!(ran, installs(&other));
+        let mut unversioned = lockfile;
+        unversioned.packages[1].source = "pacman:tool".into();
+        let err = other
+            .execute(Command::ApplyLocked {
+                lockfile: unversioned,
+                jobs: None,
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("install_version"), "{}", err);
+        assert_eq!(vec!["jq", "tool"], names(&other));
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&other_dir);
+        fs::remove_dir_all(&fixtures)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_tags() -> Res<()> {
+        let (mut manager, dir) = manager("tags");
+        manager.execute(record("a"))?;
+        manager.execute(record("b"))?;
+        let all = Query {
+            all: true,
+            ..Default::default()
+        };
+        let only_a = Query {
+            records: vec!["a".into()],
+            ..Default::default()
+        };
+        let tags = |m: &Manager| -> Vec<Vec<String>> {
+            m.records().iter().map(|r| r.tags.clone()).collect()
+        };
+
+        manager.execute(Command::Tag(TagCommand::Add {
+            tag: "dev".into(),
+            query: all.clone(),
+        }))?;
+        manager.execute(Command::Tag(TagCommand::Add {
+            tag: "work".into(),
+            query: only_a.clone(),
+        }))?;
+        assert_eq!(vec![vec!["dev", "work"], vec!["dev"]], tags(&manager));
+
+        manager.execute(Command::Tag(TagCommand::Rename {
+            from: "dev".into(),
+            to: "development".into(),
+            query: Query::default(),
+        }))?;
+        assert_eq!(
+            vec![vec!["development", "work"], vec!["development"]],
+            tags(&manager)
+        );
+
+        manager.execute(Command::Tag(TagCommand::Remove {
+            tag: "development".into(),
+            query: only_a,
+        }))?;
+        assert_eq!(vec![vec!["work"], vec!["development"]], tags(&manager));
+
+        assert!(
+            manager
+                .execute(Command::Tag(TagCommand::Remove {
+                    tag: "work".into(),
+                    query: Query::default(),
+                }))
+                .is_err()
+        );
+        assert!(
+            manager
+                .execute(Command::Tag(TagCommand::Add {
+                    tag: "not valid".into(),
+                    query: all,
+                }))
+                .is_err()
+        );
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_pin() -> Res<()> {
+        let config = sh_config(
+            r#"
+install_version = { template = "-c true {version}" }
+hold = { program = "true", template = "hold {package_name}" }
+unhold = { program = "true", template = "unhold {package_name}" }"#,
+        );
+        let (mut manager, dir) = manager_with("pin", config);
+        for (name, version) in [("a", Some("1.0.0")), ("b", None)] {
+            manager.execute(Command::Record(RecordData {
+                name: name.into(),
+                source: Some("sh".into()),
+                version: version.map(FlexibleVersion::parse),
+                ..Default::default()
+            }))?;
+        }
+
+        manager.execute(Command::Pin { id: 0, range: None })?;
+        assert_eq!(
+            Some("=1.0.0".to_string()),
+            manager.records()[0].pin.as_ref().map(|p| p.to_string())
+        );
+        assert_eq!(vec!["true hold a"], argvs(&manager));
+        assert!(
+            manager
+                .execute(Command::Pin { id: 1, range: None })
+                .is_err()
+        );
+        let below = Some("<1".parse()?);
+        assert!(
+            manager
+                .execute(Command::Pin {
+                    id: 0,
+                    range: below
+                })
+                .is_err()
+        );
+
+        manager.execute(Command::Upgrade {
+            query: Query {
+                all: true,
+                ..Default::default()
+            },
+            jobs: None,
+        })?;
+        manager.execute(apply_all())?;
+        assert_eq!(
+            vec![
+                "true hold a",
+                "sh -c true",
+                "sh -c true 1.0.0",
+                "true hold a",
+                "sh -c true"
+            ],
+            argvs(&manager)
+        );
+        let records: Vec<Vec<u32>> = manager
+            .log()
+            .entries()?
+            .iter()
+            .map(|o| o.records.clone())
+            .collect();
+        assert_eq!(vec![vec![0], vec![1], vec![0], vec![0], vec![1]], records);
+
+        manager.execute(Command::Unpin { id: 0 })?;
+        assert_eq!(None, manager.records()[0].pin);
+        assert!(manager.execute(Command::Unpin { id: 0 }).is_err());
+        assert_eq!(
+            Some("true unhold a"),
+            argvs(&manager).last().map(|s| s.as_str())
+        );
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_without_outdated() -> Res<()> {
+        let config = sh_config(r#"list = { program = "echo", template = "tool 2.0.0" }"#);
+        let (mut manager, dir) = manager_with("upgrade-list", config);
+        for name in ["tool", "unlisted"] {
+            manager.execute(Command::Record(RecordData {
+                name: name.into(),
+                source: Some("sh".into()),
+                version: Some(FlexibleVersion::parse("1.0.0")),
+                ..Default::default()
+            }))?;
+        }
+        manager.execute(Command::Upgrade {
+            query: Query {
+                all: true,
+                ..Default::default()
+            },
+            jobs: None,
+        })?;
+        // 升级后的版本从列表里读，列表里没有的保留原来的
+        let versions: Vec<String> = manager
+            .records()
+            .iter()
+            .map(|r| r.version.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(vec!["2.0.0", "1.0.0"], versions);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_outdated() -> Res<()> {
+        let fixtures = fixtures("outdated");
+        let listing = fixtures.join("outdated.txt");
+        fs::write(&listing, "a (1.0.0) < 1.1.0\nb 2.0.5\nc 1.0.0\nd 2.1.0\n")?;
+        let config = sh_config(&format!(
+            r#"
+install_version = {{ template = "-c true {{version}}" }}
+outdated = {{ program = "cat", template = "{}" }}"#,
+            listing.display()
+        ));
+        let (mut manager, dir) = manager_with("upgrade", config);
+        for (name, version, pin) in [
+            ("a", "1.0.0", None),
+            ("b", "2.0.0", Some("~2.0")),
+            ("c", "v1.0", None),
+            ("d", "1.0.0", Some("^1")),
+        ] {
+            manager.execute(Command::Record(RecordData {
+                name: name.into(),
+                source: Some("sh".into()),
+                version: Some(FlexibleVersion::parse(version)),
+                pin: pin.map(|p| p.parse().unwrap()),
+                ..Default::default()
+            }))?;
+        }
+
+        let outdated = manager.outdated(&Query::default())?;
+        let available: Vec<String> = outdated
+            .iter()
+            .map(|o| format!("{} {}", o.name, o.available))
+            .collect();
+        assert_eq!(vec!["a 1.1.0", "b 2.0.5", "d 2.1.0"], available);
+        assert!(!outdated[2].allowed());
+
+        manager.execute(Command::Upgrade {
+            query: Query {
+                all: true,
+                ..Default::default()
+            },
+            jobs: None,
+        })?;
+        let versions: Vec<String> = manager
+            .records()
+            .iter()
+            .map(|r| r.version.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(vec!["1.1.0", "2.0.5", "v1.0", "1.0.0"], versions);
+        let args: Vec<String> = manager
+            .log()
+            .entries()?
+            .iter()
+            .map(|o| o.argv[1..].join(" "))
+            .collect();
+        assert_eq!(
+            vec![
+                listing.display().to_string(),
+                listing.display().to_string(),
+                "-c true".into(),
+                "-c true 2.0.5".into()
+            ],
+            args
+        );
+
+        fs::remove_dir_all(&fixtures)?;
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_manager_queries() -> Res<()> {
+        let fixtures = fixtures("queries");
+        fs::write(fixtures.join("list.txt"), "a 1.0.0\nb 2.0\n")?;
+        fs::write(
+            fixtures.join("search-rip.txt"),
+            "Sorting...\nripgrep/stable 14.1.0-1 amd64\nripgrep-all/stable 0.10.6 amd64\n",
+        )?;
+        let config = sh_config(&format!(
+            r#"
+list = {{ program = "cat", template = "{dir}/list.txt" }}
+search = {{ program = "cat", template = "{dir}/search-{{package_name}}.txt", parse = {{ regex = '^(?P<name>[^/\s]+)/\S+ (?P<version>\S+)' }} }}
+info = {{ program = "echo", template = "about {{package_name}}" }}
+clean = {{ program = "true", template = "clean" }}"#,
+            dir = fixtures.display()
+        ));
+        let (mut manager, dir) = manager_with("queries", config);
+        manager.execute(Command::Record(RecordData {
+            name: "a".into(),
+            source: Some("sh".into()),
+            ..Default::default()
+        }))?;
+
+        let installed: Vec<String> = manager
+            .installed("sh")?
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(vec!["a 1.0.0", "b 2.0"], installed);
+        let Listing::Packages(found) = manager.search("sh", "rip")? else {
+            panic!("search has a parser");
+        };
+        let found: Vec<String> = found.iter().map(|p| p.to_string()).collect();
+        assert_eq!(vec!["ripgrep 14.1.0-1", "ripgrep-all 0.10.6"], found);
+        assert_eq!(Listing::Raw("about a\n".into()), manager.info("a")?);
+
+        manager.execute(Command::Clean { managers: vec![] })?;
+        assert_eq!(
+            vec!["true", "clean"],
+            manager.log().entries()?.last().unwrap().argv
+        );
+        manager.execute(Command::Refresh { managers: vec![] })?;
+        let refresh = manager.execute(Command::Refresh {
+            managers: vec!["sh".into()],
+        });
+        assert!(
+            refresh
+                .unwrap_err()
+                .to_string()
+                .contains("no refresh command")
+        );
+
+        fs::remove_dir_all(&fixtures)?;
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo() -> Res<()> {
+        let (mut manager, dir) = manager("undo");
+        manager.execute(record("a"))?;
+        manager.execute(record("b"))?;
+        manager.execute(Command::Remove {
+            id: 0,
+            record_only: true,
+        })?;
+        assert_eq!(vec!["b"], names(&manager));
+
+        manager.execute(Command::Undo { inverse: false })?;
+        assert_eq!(vec!["a", "b"], names(&manager));
+
+        manager.execute(Command::Clear)?;
+        assert!(manager.records().is_empty());
+        manager.execute(Command::Undo { inverse: false })?;
+        assert_eq!(vec!["a", "b"], names(&manager));
+
+        // 撤销已经撤销过的操作之前的那一次
+        manager.execute(Command::Undo { inverse: false })?;
+        assert_eq!(vec!["a"], names(&manager));
+        manager.execute(Command::Undo { inverse: false })?;
+        assert!(manager.records().is_empty());
+        assert!(manager.execute(Command::Undo { inverse: false }).is_err());
+
+        let history: Vec<String> = manager
+            .journal()
+            .history("a")?
+            .iter()
+            .map(|e| format!("{} via {}", e.event, e.command))
+            .collect();
+        assert_eq!(
+            vec![
+                "created via record a",
+                "deleted via remove 0",
+                "created via undo `remove 0`",
+                "deleted via clear",
+                "created via undo `clear`",
+                "deleted via undo `record a`",
+            ],
+            history
+        );
+
+        let saved = DataManager::new(&dir.join("records.json"))?;
+        assert!(saved.db.data.is_empty());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 }
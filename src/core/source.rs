@@ -0,0 +1,338 @@
+use std::str::FromStr;
+
+use color_eyre::{
+    Report, Result as Res,
+    eyre::{Context, bail, ensure, eyre},
+};
+
+use crate::core::{data::RecordData, version::VersionRange};
+
+/// where a package comes from, written as one argument
+///
+/// # Supported forms:
+/// - `apt:ripgrep`, `cargo:ripgrep@14`, `npm:@types/node@20`
+/// - `flatpak:flathub/org.gimp.GIMP`, with the remote before the last `/`
+/// - `git+https://host/repo`, `git+https://host/repo#v1.0` with a tag, branch or commit
+/// - `https://host/tool.tar.gz`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Package {
+        manager: String,
+        remote: Option<String>,
+        name: String,
+        version: Option<String>,
+    },
+    Git {
+        url: String,
+        reference: Option<String>,
+    },
+    Url(String),
+}
+
+/// manager name of url sources
+pub const URL: &str = "url";
+/// manager name of git sources
+pub const GIT: &str = "git";
+
+/// archive extensions dropped from the file name of a url source
+const ARCHIVES: [&str; 8] = [
+    ".tar.gz", ".tgz", ".tar.xz", ".txz", ".tar.bz2", ".tbz2", ".tar", ".zip",
+];
+
+impl Source {
+    /// the manager that installs it, `git` and `url` for those sources
+    pub fn manager(&self) -> &str {
+        match self {
+            Self::Package { manager, .. } => manager,
+            Self::Git { .. } => GIT,
+            Self::Url(_) => URL,
+        }
+    }
+
+    /// the last segment of the url path, or the name of a package
+    pub fn file_name(&self) -> &str {
+        match self {
+            Self::Package { name, .. } => name,
+            Self::Git { url, .. } | Self::Url(url) => last_segment(url),
+        }
+    }
+
+    /// the package name, the repository or file name for git and url sources
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Package { name, .. } => name,
+            Self::Git { url, .. } => last_segment(url).trim_end_matches(".git"),
+            Self::Url(_) => {
+                let file = self.file_name();
+                ARCHIVES
+                    .iter()
+                    .find_map(|ext| file.strip_suffix(ext))
+                    .unwrap_or(file)
+            }
+        }
+    }
+
+    /// the remote of the manager, or the url of a git or url source, kept as `origin` of a record
+    pub fn origin(&self) -> Option<String> {
+        match self {
+            Self::Package { remote, .. } => remote.clone(),
+            Self::Git { url, reference } => Some(match reference {
+                Some(reference) => format!("{}#{}", url, reference),
+                None => url.clone(),
+            }),
+            Self::Url(url) => Some(url.clone()),
+        }
+    }
+
+    /// the same source without a version, which only packages have
+    pub fn unversioned(mut self) -> Self {
+        if let Self::Package { version, .. } = &mut self {
+            *version = None;
+        }
+        self
+    }
+
+    /// a new record of the package, not installed yet, pinned to the version asked for
+    pub fn record(&self) -> RecordData {
+        let pin = match self {
+            // 解析时已经检查过
+            Self::Package { version, .. } => version.as_deref().and_then(|v| v.parse().ok()),
+            _ => None,
+        };
+        RecordData {
+            name: self.name().to_string(),
+            pin,
+            source: Some(self.manager().to_string()),
+            origin: self.origin(),
+            ..Default::default()
+        }
+    }
+
+    /// the source a record was made from, `None` if it has no source
+    pub fn of(record: &RecordData) -> Option<Self> {
+        let manager = record.source.as_ref()?;
+        Some(match (manager.as_str(), record.origin.as_ref()) {
+            (GIT, Some(origin)) => {
+                let (url, reference) = split_reference(origin);
+                Self::Git { url, reference }
+            }
+            (URL, Some(origin)) => Self::Url(origin.clone()),
+            (_, remote) => Self::Package {
+                manager: manager.clone(),
+                remote: remote.cloned(),
+                name: record.name.clone(),
+                version: record.version.as_ref().map(|v| v.to_string()),
+            },
+        })
+    }
+}
+
+/// the last path segment of a url, without the query and fragment
+fn last_segment(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(path)
+}
+
+/// `url#reference`
+fn split_reference(s: &str) -> (String, Option<String>) {
+    match s.rsplit_once('#') {
+        Some((url, reference)) => (url.to_string(), Some(reference.to_string())),
+        None => (s.to_string(), None),
+    }
+}
+
+impl FromStr for Source {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Res<Self> {
+        let s = s.trim();
+        ensure!(
+            !s.is_empty() && !s.contains(char::is_whitespace),
+            "invalid source `{}`, it must not be empty or contain spaces",
+            s
+        );
+        if let Some(url) = s.strip_prefix("git+") {
+            let (url, reference) = split_reference(url);
+            ensure!(url.contains("://"), "`{}` is not a git url", s);
+            ensure!(
+                reference.as_ref().is_none_or(|r| !r.is_empty()),
+                "`{}` has an empty reference after `#`",
+                s
+            );
+            let source = Self::Git { url, reference };
+            ensure!(!source.name().is_empty(), "`{}` has no repository name", s);
+            return Ok(source);
+        }
+        if s.starts_with("http://") || s.starts_with("https://") {
+            let source = Self::Url(s.to_string());
+            ensure!(
+                !source.name().is_empty() && s.split_once("://").unwrap().1.contains('/'),
+                "`{}` has no file name",
+                s
+            );
+            return Ok(source);
+        }
+
+        let (manager, package) = s.split_once(':').ok_or_else(|| {
+            eyre!(
+                "`{}` has no manager, write it as <manager>:{} e.g. cargo:{}",
+                s,
+                s,
+                s
+            )
+        })?;
+        if manager.is_empty()
+            || !manager
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("invalid manager `{}` in `{}`", manager, s);
+        }
+        // 开头的 @ 是 npm 的 scope，不是版本
+        let (package, version) = match package.rsplit_once('@') {
+            Some((package, version)) if !package.is_empty() => {
+                ensure!(
+                    !version.is_empty(),
+                    "`{}` has an empty version after `@`",
+                    s
+                );
+                version
+                    .parse::<VersionRange>()
+                    .with_context(|| format!("invalid version in `{}`", s))?;
+                (package, Some(version.to_string()))
+            }
+            _ => (package, None),
+        };
+        let (remote, name) = match package.rsplit_once('/') {
+            Some((remote, name)) if !remote.starts_with('@') => {
+                ensure!(!remote.is_empty(), "`{}` has an empty remote", s);
+                (Some(remote.to_string()), name)
+            }
+            _ => (None, package),
+        };
+        ensure!(!name.is_empty(), "`{}` has no package name", s);
+        Ok(Self::Package {
+            manager: manager.to_string(),
+            remote,
+            name: name.to_string(),
+            version,
+        })
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Package {
+                manager,
+                remote,
+                name,
+                version,
+            } => {
+                write!(f, "{}:", manager)?;
+                if let Some(remote) = remote {
+                    write!(f, "{}/", remote)?;
+                }
+                write!(f, "{}", name)?;
+                if let Some(version) = version {
+                    write!(f, "@{}", version)?;
+                }
+                Ok(())
+            }
+            Self::Git { .. } => write!(f, "git+{}", self.origin().unwrap_or_default()),
+            Self::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Res<()> {
+        for (input, manager, name, origin) in [
+            ("apt:ripgrep", "apt", "ripgrep", None),
+            ("cargo:ripgrep@14", "cargo", "ripgrep", None),
+            ("npm:@types/node@20.1", "npm", "@types/node", None),
+            (
+                "flatpak:flathub/org.gimp.GIMP",
+                "flatpak",
+                "org.gimp.GIMP",
+                Some("flathub"),
+            ),
+            (
+                "git+https://github.com/BurntSushi/ripgrep.git#14.1.0",
+                "git",
+                "ripgrep",
+                Some("https://github.com/BurntSushi/ripgrep.git#14.1.0"),
+            ),
+            (
+                "git+ssh://git@host/me/dotfiles",
+                "git",
+                "dotfiles",
+                Some("ssh://git@host/me/dotfiles"),
+            ),
+            (
+                "https://example.com/releases/tool.tar.gz",
+                "url",
+                "tool",
+                Some("https://example.com/releases/tool.tar.gz"),
+            ),
+            (
+                "http://127.0.0.1:8080/bin/fd?raw=1",
+                "url",
+                "fd",
+                Some("http://127.0.0.1:8080/bin/fd?raw=1"),
+            ),
+        ] {
+            let source: Source = input.parse()?;
+            assert_eq!(manager, source.manager(), "{}", input);
+            assert_eq!(name, source.name(), "{}", input);
+            assert_eq!(origin.map(String::from), source.origin(), "{}", input);
+            // 解析后再写出来应该一模一样
+            assert_eq!(input, source.to_string());
+            let record = source.record();
+            assert_eq!(Some(source.clone().unversioned()), Source::of(&record));
+            assert_eq!(
+                (manager, origin),
+                (record.source.as_deref().unwrap(), record.origin.as_deref())
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_record() -> Res<()> {
+        let record = "cargo:ripgrep@14".parse::<Source>()?.record();
+        assert_eq!("ripgrep", record.name);
+        assert_eq!(Some("cargo".to_string()), record.source);
+        assert_eq!(None, record.version);
+        assert_eq!(Some("14".parse()?), record.pin);
+        assert_eq!(None, record.origin);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        for input in [
+            "",
+            "ripgrep",
+            ":ripgrep",
+            "apt:",
+            "cargo:ripgrep@",
+            "cargo:ripgrep@>=x",
+            "flatpak:/org.gimp.GIMP",
+            "c++:thing",
+            "apt:rip grep",
+            "git+https://host/repo#",
+            "git+repo",
+            "https://example.com",
+        ] {
+            assert!(input.parse::<Source>().is_err(), "{} should fail", input);
+        }
+    }
+}
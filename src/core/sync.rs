@@ -0,0 +1,403 @@
+use std::collections::{BTreeSet, HashMap};
+
+use colored::Colorize;
+use serde_json::{Map, Value};
+
+use crate::core::data::RecordData;
+
+/// the records in the sync repository, the same format as the database
+pub const FILE: &str = "records.json";
+
+/// the branch records are synced on
+pub const BRANCH: &str = "main";
+
+/// the remote commit last merged into the records, the base of the next merge
+pub const BASE_REF: &str = "refs/fmn/base";
+
+/// which copy of a record was kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+impl std::fmt::Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local => write!(f, "this one"),
+            Self::Remote => write!(f, "the remote one"),
+        }
+    }
+}
+
+/// a record both sides touched since the last sync
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// both changed a field of it differently, the value of the side updated last was kept
+    Field {
+        id: u32,
+        name: String,
+        field: String,
+        local: String,
+        remote: String,
+        kept: Side,
+    },
+    /// one removed it and the other changed it, the changed one was kept
+    Removed { id: u32, name: String, kept: Side },
+    /// both added a different record with this id, the local one moved to `to`,
+    /// its history and operations with it
+    Renumbered { from: u32, to: u32, name: String },
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Field {
+                id,
+                name,
+                field,
+                local,
+                remote,
+                kept,
+            } => write!(
+                f,
+                "{:>4}  {}  {} is {} here and {} on the remote, kept {} as it changed last, change it if the other is right",
+                id, name, field, local, remote, kept
+            ),
+            Self::Removed { id, name, kept } => write!(
+                f,
+                "{:>4}  {}  removed on one side and changed on the other, kept {}",
+                id, name, kept
+            ),
+            Self::Renumbered { from, to, name } => write!(
+                f,
+                "{:>4}  {}  added here and on the remote under the same id, this one is now {}",
+                from, name, to
+            ),
+        }
+    }
+}
+
+/// the records after merging, and what could not be merged cleanly
+#[derive(Debug, Clone, Default)]
+pub struct Merged {
+    pub records: HashMap<u32, RecordData>,
+    pub conflicts: Vec<Conflict>,
+    /// the first id neither side used
+    pub next_id: u32,
+}
+
+/// whether two records are of the same package on the same host
+fn same_package(a: &RecordData, b: &RecordData) -> bool {
+    a.name == b.name
+        && a.source == b.source
+        && match (&a.host, &b.host) {
+            (Some(a), Some(b)) => a.is(b),
+            (a, b) => a == b,
+        }
+}
+
+/// a field of a record as json, `null` if it is not there
+fn field<'a>(record: &'a Map<String, Value>, key: &str) -> &'a Value {
+    record.get(key).unwrap_or(&Value::Null)
+}
+
+/// how a field value is shown in a conflict
+fn show(value: &Value) -> String {
+    match value {
+        Value::Null => "none".into(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn to_map(record: &RecordData) -> Map<String, Value> {
+    match serde_json::to_value(record) {
+        Ok(Value::Object(map)) => map,
+        _ => unreachable!("a record is always a json object"),
+    }
+}
+
+/// tags added on either side are added, tags removed on either side are removed
+fn merge_tags(base: &[String], local: &[String], remote: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = local
+        .iter()
+        .filter(|t| remote.contains(t) || !base.contains(t))
+        .cloned()
+        .collect();
+    for tag in remote {
+        if !base.contains(tag) && !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    tags
+}
+
+/// merge a record both sides changed field by field against `base`,
+/// a field both changed differently keeps the value of the side updated last and is reported
+fn merge_fields(
+    base: Option<&RecordData>,
+    local: &RecordData,
+    remote: &RecordData,
+    conflicts: &mut Vec<Conflict>,
+) -> RecordData {
+    let b = base.map(to_map).unwrap_or_default();
+    let (l, r) = (to_map(local), to_map(remote));
+    let keys: BTreeSet<&String> = b.keys().chain(l.keys()).chain(r.keys()).collect();
+    // 同时更新的话留本地的
+    let kept = match remote.updated > local.updated {
+        true => Side::Remote,
+        false => Side::Local,
+    };
+    let mut merged = Map::new();
+    for key in keys {
+        let (bv, lv, rv) = (field(&b, key), field(&l, key), field(&r, key));
+        // 标签和时间在下面另外合并
+        let value = if lv == rv || rv == bv || key == "tags" || key == "updated" {
+            lv
+        } else if lv == bv {
+            rv
+        } else {
+            conflicts.push(Conflict::Field {
+                id: local.id,
+                name: local.name.clone(),
+                field: key.clone(),
+                local: show(lv),
+                remote: show(rv),
+                kept,
+            });
+            match kept {
+                Side::Local => lv,
+                Side::Remote => rv,
+            }
+        };
+        merged.insert(key.clone(), value.clone());
+    }
+    let mut record: RecordData = serde_json::from_value(Value::Object(merged))
+        .expect("the fields of two records form a record");
+    record.tags = merge_tags(base.map_or(&[][..], |b| &b.tags), &local.tags, &remote.tags);
+    record.updated = local.updated.max(remote.updated);
+    record
+}
+
+/// merge the records of two hosts, `base` is how they were at the last sync,
+/// `next_id` is the first id neither side handed out
+///
+/// a record changed on one side only takes that change, one changed on both is merged
+/// field by field, see [`Conflict`] for what cannot be merged
+pub fn merge(
+    base: &HashMap<u32, RecordData>,
+    local: &HashMap<u32, RecordData>,
+    remote: &HashMap<u32, RecordData>,
+    next_id: u32,
+) -> Merged {
+    let ids: BTreeSet<u32> = base
+        .keys()
+        .chain(local.keys())
+        .chain(remote.keys())
+        .copied()
+        .collect();
+    let mut next_id = ids.last().map_or(0, |id| id + 1).max(next_id);
+    let mut merged = Merged::default();
+    let mut renumbered = Vec::new();
+    for id in ids {
+        let (b, l, r) = (base.get(&id), local.get(&id), remote.get(&id));
+        let kept = if l == r || l == b {
+            r
+        } else if r == b {
+            l
+        } else {
+            match (l, r) {
+                (Some(l), Some(r)) if b.is_none() && !same_package(l, r) => {
+                    // 两边都新加了记录，本地的换一个 id
+                    merged.conflicts.push(Conflict::Renumbered {
+                        from: id,
+                        to: next_id,
+                        name: l.name.clone(),
+                    });
+                    renumbered.push(RecordData {
+                        id: next_id,
+                        ..l.clone()
+                    });
+                    next_id += 1;
+                    Some(r)
+                }
+                (Some(l), Some(r)) => {
+                    let record = merge_fields(b, l, r, &mut merged.conflicts);
+                    merged.records.insert(id, record);
+                    None
+                }
+                (Some(record), None) | (None, Some(record)) => {
+                    merged.conflicts.push(Conflict::Removed {
+                        id,
+                        name: record.name.clone(),
+                        kept: if l.is_some() {
+                            Side::Local
+                        } else {
+                            Side::Remote
+                        },
+                    });
+                    Some(record)
+                }
+                (None, None) => None,
+            }
+        };
+        if let Some(record) = kept {
+            merged.records.insert(id, record.clone());
+        }
+    }
+    for record in renumbered {
+        merged.records.insert(record.id, record);
+    }
+    merged.next_id = next_id;
+    merged
+}
+
+/// one row per conflict, in yellow
+pub fn table(conflicts: &[Conflict]) -> Vec<String> {
+    conflicts
+        .iter()
+        .map(|c| c.to_string().yellow().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn record(id: u32, name: &str, tags: &[&str], updated: i64) -> RecordData {
+        RecordData {
+            id,
+            name: name.into(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            updated: Utc.timestamp_opt(updated, 0).single(),
+            ..Default::default()
+        }
+    }
+
+    fn map(records: &[RecordData]) -> HashMap<u32, RecordData> {
+        records.iter().map(|r| (r.id, r.clone())).collect()
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = map(&[
+            record(0, "kept", &[], 0),
+            record(1, "tagged-here", &[], 0),
+            record(2, "tagged-there", &[], 0),
+            record(3, "removed-there", &[], 0),
+            record(4, "both", &[], 0),
+            record(5, "removed-here", &[], 0),
+            record(8, "fields", &["old"], 0),
+        ]);
+        let local = map(&[
+            record(0, "kept", &[], 0),
+            record(1, "tagged-here", &["a"], 10),
+            record(2, "tagged-there", &[], 0),
+            record(3, "removed-there", &[], 0),
+            RecordData {
+                description: Some("here".into()),
+                ..record(4, "both", &["late"], 30)
+            },
+            RecordData {
+                description: Some("described".into()),
+                ..record(8, "fields", &[], 10)
+            },
+            record(6, "new-here", &[], 10),
+        ]);
+        let remote = map(&[
+            record(0, "kept", &[], 0),
+            record(1, "tagged-here", &[], 0),
+            record(2, "tagged-there", &["b"], 20),
+            RecordData {
+                description: Some("there".into()),
+                ..record(4, "both", &["early"], 20)
+            },
+            RecordData {
+                description: Some("remote".into()),
+                ..record(8, "fields", &["old", "new"], 20)
+            },
+            record(5, "removed-here", &["b"], 20),
+            record(6, "new-there", &[], 20),
+            record(7, "newer-there", &[], 20),
+        ]);
+        let merged = merge(&base, &local, &remote, 0);
+        let mut records: Vec<(u32, &str, Vec<String>, Option<&str>)> = merged
+            .records
+            .values()
+            .map(|r| {
+                (
+                    r.id,
+                    r.name.as_str(),
+                    r.tags.clone(),
+                    r.description.as_deref(),
+                )
+            })
+            .collect();
+        records.sort();
+        let tags = |t: &[&str]| t.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (0, "kept", tags(&[]), None),
+                (1, "tagged-here", tags(&["a"]), None),
+                (2, "tagged-there", tags(&["b"]), None),
+                // 两边都改了描述，留下后改的
+                (4, "both", tags(&["late", "early"]), Some("here")),
+                (5, "removed-here", tags(&["b"]), None),
+                (6, "new-there", tags(&[]), None),
+                (7, "newer-there", tags(&[]), None),
+                // 标签两边的都留下，描述是远程后改的
+                (8, "fields", tags(&["new"]), Some("remote")),
+                (9, "new-here", tags(&[]), None),
+            ],
+            records
+        );
+        assert_eq!(
+            vec![
+                Conflict::Field {
+                    id: 4,
+                    name: "both".into(),
+                    field: "description".into(),
+                    local: "here".into(),
+                    remote: "there".into(),
+                    kept: Side::Local,
+                },
+                Conflict::Removed {
+                    id: 5,
+                    name: "removed-here".into(),
+                    kept: Side::Remote
+                },
+                Conflict::Renumbered {
+                    from: 6,
+                    to: 9,
+                    name: "new-here".into()
+                },
+                Conflict::Field {
+                    id: 8,
+                    name: "fields".into(),
+                    field: "description".into(),
+                    local: "described".into(),
+                    remote: "remote".into(),
+                    kept: Side::Remote,
+                },
+            ],
+            merged.conflicts
+        );
+
+        // 同一个包在两边都加了，合成一条
+        let merged = merge(
+            &HashMap::new(),
+            &map(&[record(0, "rg", &["old"], 10)]),
+            &map(&[record(0, "rg", &["new"], 20)]),
+            0,
+        );
+        assert_eq!(vec!["old", "new"], merged.records[&0].tags);
+        assert_eq!(
+            Utc.timestamp_opt(20, 0).single(),
+            merged.records[&0].updated
+        );
+        assert_eq!(1, merged.records.len());
+        assert!(merged.conflicts.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+
+use crate::core::{data::RecordData, download};
+
+/// the binary of `name` in the output of a `files` command, one path per line
+///
+/// only executables in a `bin` or `sbin` directory count, the one called `name` first
+pub fn binary_in(files: &str, name: &str) -> Option<PathBuf> {
+    let binaries: Vec<&Path> = files
+        .lines()
+        .map(|line| Path::new(line.trim()))
+        .filter(|path| {
+            path.parent()
+                .and_then(|dir| dir.file_name())
+                .is_some_and(|dir| dir == "bin" || dir == "sbin")
+                && download::is_executable(path)
+        })
+        .collect();
+    binaries
+        .iter()
+        .find(|path| path.file_name().is_some_and(|file| file == name))
+        .or(binaries.first())
+        .map(|path| path.to_path_buf())
+}
+
+/// what is wrong with an install, if anything
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// no location was recorded
+    Unknown,
+    Missing,
+    /// gone from its location, but a file of the same name is in PATH
+    Moved(PathBuf),
+    NotExecutable,
+    /// its sha256 is not the recorded checksum
+    Modified,
+}
+
+impl Status {
+    pub fn is_broken(&self) -> bool {
+        !matches!(self, Self::Ok | Self::Unknown)
+    }
+}
+
+/// the install of a record, as found on this host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verified {
+    pub id: u32,
+    pub name: String,
+    pub location: Option<PathBuf>,
+    pub status: Status,
+}
+
+/// check that the location of `record` exists, can be run, and still has its checksum
+pub fn check(record: &RecordData) -> Verified {
+    let status = match &record.location {
+        None => Status::Unknown,
+        Some(location) if !location.exists() => location
+            .file_name()
+            .and_then(|file| which::which(file).ok())
+            .map_or(Status::Missing, Status::Moved),
+        // 目录只检查是否存在，例如 flatpak 的安装目录
+        Some(location) if location.is_file() && !download::is_executable(location) => {
+            Status::NotExecutable
+        }
+        Some(location)
+            if location.is_file()
+                && record.checksum.as_ref().is_some_and(|expected| {
+                    download::sha256(location)
+                        .is_ok_and(|actual| !actual.eq_ignore_ascii_case(expected))
+                }) =>
+        {
+            Status::Modified
+        }
+        Some(_) => Status::Ok,
+    };
+    Verified {
+        id: record.id,
+        name: record.name.clone(),
+        location: record.location.clone(),
+        status,
+    }
+}
+
+/// aligned rows: name, location, and what is wrong, broken installs in red
+pub fn table(verified: &[Verified]) -> Vec<String> {
+    let width = verified
+        .iter()
+        .map(|v| v.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    verified
+        .iter()
+        .map(|v| {
+            let location = v
+                .location
+                .as_ref()
+                .map_or(String::new(), |l| l.display().to_string());
+            let row = format!("{:<width$}  {}", v.name, location);
+            match &v.status {
+                Status::Ok => format!("✓ {}", row),
+                Status::Unknown => format!("  {:<width$}  no location recorded", v.name)
+                    .dimmed()
+                    .to_string(),
+                Status::Missing => format!("✗ {}  missing", row).red().to_string(),
+                Status::Moved(to) => format!("✗ {}  moved to {}", row, to.display())
+                    .yellow()
+                    .to_string(),
+                Status::NotExecutable => format!("✗ {}  not executable", row).red().to_string(),
+                Status::Modified => format!("✗ {}  checksum mismatch", row).red().to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_binary_in() -> color_eyre::Result<()> {
+        let dir = std::env::temp_dir().join(format!("fmn-verify-bin-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("share/doc/ripgrep"))?;
+        fs::write(dir.join("share/doc/ripgrep/README"), "")?;
+        let readme = dir.join("share/doc/ripgrep/README");
+        let rg = download::place(&readme, &dir.join("bin"), "rg")?;
+        let ripgrep = download::place(&readme, &dir.join("sbin"), "ripgrep")?;
+        let files = format!(
+            "{}\n{}\n{}\n{}\n",
+            dir.join("bin").display(),
+            readme.display(),
+            rg.display(),
+            ripgrep.display()
+        );
+        assert_eq!(Some(ripgrep), binary_in(&files, "ripgrep"));
+        assert_eq!(Some(rg.clone()), binary_in(&files, "other"));
+        assert_eq!(None, binary_in(&format!("{}\n", readme.display()), "rg"));
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_check() -> color_eyre::Result<()> {
+        colored::control::set_override(false);
+        let dir = std::env::temp_dir().join(format!("fmn-verify-check-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("notes"), "abc")?;
+        let tool = download::place(&dir.join("notes"), &dir.join("bin"), "fmn-verify-tool")?;
+        let record = |location: Option<PathBuf>, checksum: Option<&str>| RecordData {
+            name: "tool".into(),
+            location,
+            checksum: checksum.map(String::from),
+            ..Default::default()
+        };
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+        let statuses: Vec<Status> = [
+            record(Some(tool.clone()), Some(abc)),
+            record(Some(dir.clone()), None),
+            record(None, None),
+            record(Some(dir.join("gone")), None),
+            record(Some(dir.join("notes")), None),
+            record(Some(tool.clone()), Some("00")),
+        ]
+        .iter()
+        .map(|r| check(r).status)
+        .collect();
+        assert_eq!(
+            vec![
+                Status::Ok,
+                Status::Ok,
+                Status::Unknown,
+                Status::Missing,
+                Status::NotExecutable,
+                Status::Modified
+            ],
+            statuses
+        );
+        // 同名文件还在 PATH 里，说明被挪走了
+        assert_eq!(
+            Status::Moved(which::which("sh")?),
+            check(&record(Some(dir.join("sh")), None)).status
+        );
+
+        let rows = table(&[
+            check(&record(Some(tool.clone()), None)),
+            check(&record(Some(dir.join("gone")), None)),
+            check(&record(None, None)),
+        ]);
+        assert_eq!(
+            vec![
+                format!("✓ tool  {}", tool.display()),
+                format!("✗ tool  {}  missing", dir.join("gone").display()),
+                "  tool  no location recorded".to_string(),
+            ],
+            rows
+        );
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,721 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use color_eyre::eyre::{Report, bail, eyre};
+use semver::{BuildMetadata, Prerelease, Version};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Stored")]
+pub enum FlexibleVersion {
+    Semantic(Version),
+    /// coerced to semver, but displayed as written, e.g. `v1.2`
+    Normalized {
+        version: Version,
+        original: String,
+    },
+    Raw(String),
+}
+
+/// what may be found in a saved database
+///
+/// `Sematic` was the old spelling of `Semantic`,
+/// and `Raw` versions are normalized again since older releases did not do it
+#[derive(Deserialize)]
+enum Stored {
+    #[serde(alias = "Sematic")]
+    Semantic(Version),
+    Normalized {
+        version: Version,
+        original: String,
+    },
+    Raw(String),
+}
+
+impl From<Stored> for FlexibleVersion {
+    fn from(stored: Stored) -> Self {
+        match stored {
+            Stored::Semantic(version) => Self::Semantic(version),
+            Stored::Normalized { version, original } => Self::Normalized { version, original },
+            Stored::Raw(s) => Self::parse(&s),
+        }
+    }
+}
+
+/// pre-release tags that may follow a `-`, like `1.2.0-rc1`
+const PRE_RELEASE_TAGS: &[&str] = &["alpha", "beta", "rc", "pre", "preview", "dev"];
+
+/// a package revision like `r1` or `p2`
+fn is_revision(s: &str) -> bool {
+    s.strip_prefix(['r', 'p'])
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// coerce common non-semver forms, `None` if it cannot be done without changing the order
+///
+/// - `v1.2` and `1.2` as 1.2.0
+/// - `1.2.3.4` as 1.2.3+4
+/// - `1.2.3-r1` as 1.2.3+r1, a package revision sorts after the release
+/// - `1.2-rc1` as 1.2.0-rc1
+fn normalize(s: &str) -> Option<Version> {
+    let s = s
+        .strip_prefix(['v', 'V'])
+        .filter(|s| s.starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap_or(s);
+    let (s, build) = s.split_once('+').unwrap_or((s, ""));
+    let (core, suffix) = s.split_once('-').unwrap_or((s, ""));
+
+    let parts: Vec<u64> = core
+        .split('.')
+        .map(|p| {
+            p.parse()
+                .ok()
+                .filter(|_| p.chars().all(|c| c.is_ascii_digit()))
+        })
+        .collect::<Option<_>>()?;
+    if parts.len() > 4 {
+        return None;
+    }
+    let mut builds: Vec<&str> = Vec::new();
+    if parts.len() == 4 {
+        builds.push(core.rsplit('.').next()?);
+    }
+
+    let mut pre = Prerelease::EMPTY;
+    if !suffix.is_empty() {
+        let tag = suffix.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if is_revision(suffix) {
+            builds.push(suffix);
+        } else if PRE_RELEASE_TAGS.contains(&tag.to_lowercase().as_str()) {
+            pre = Prerelease::new(suffix).ok()?;
+        } else {
+            return None;
+        }
+    }
+    if !build.is_empty() {
+        builds.push(build);
+    }
+
+    let part = |i: usize| parts.get(i).copied().unwrap_or(0);
+    Some(Version {
+        major: part(0),
+        minor: part(1),
+        patch: part(2),
+        pre,
+        build: BuildMetadata::new(&builds.join(".")).ok()?,
+    })
+}
+
+impl FlexibleVersion {
+    /// strict semver first, then the forms known to [`normalize`], anything else is kept raw
+    pub fn parse(s: &str) -> Self {
+        let s = s.trim();
+        // `1.2.3-r1` 也是合法的 semver，但 r1 是打包修订号而不是预发布
+        if let Ok(version) = Version::parse(s)
+            && !is_revision(version.pre.as_str())
+        {
+            return Self::Semantic(version);
+        }
+        match normalize(s) {
+            Some(version) => Self::Normalized {
+                version,
+                original: s.to_string(),
+            },
+            None => Self::Raw(s.to_string()),
+        }
+    }
+
+    pub fn semver(&self) -> Option<&Version> {
+        match self {
+            Self::Semantic(version) | Self::Normalized { version, .. } => Some(version),
+            Self::Raw(_) => None,
+        }
+    }
+
+    /// the version written the way dpkg compares it, pre-releases sort before the release
+    fn debian(&self) -> String {
+        match self.semver() {
+            Some(ver) => {
+                let mut s = format!("{}.{}.{}", ver.major, ver.minor, ver.patch);
+                if !ver.pre.is_empty() {
+                    s.push('~');
+                    s.push_str(ver.pre.as_str());
+                }
+                if !ver.build.is_empty() {
+                    s.push('.');
+                    s.push_str(ver.build.as_str());
+                }
+                s
+            }
+            None => self.to_string(),
+        }
+    }
+
+    /// the text as written, without a leading `v`
+    fn written(&self) -> String {
+        let s = self.to_string();
+        match s.strip_prefix(['v', 'V']) {
+            Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => rest.to_string(),
+            _ => s,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Semantic(_) => 0,
+            Self::Normalized { .. } => 1,
+            Self::Raw(_) => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for FlexibleVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Semantic(ver) => write!(f, "{}", ver),
+            Self::Normalized { original, .. } => write!(f, "{}", original),
+            Self::Raw(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl FlexibleVersion {
+    /// semver precedence between two semver versions, dpkg ordering otherwise
+    ///
+    /// build metadata holds package revisions like `r2`, so it is compared too,
+    /// versions only differing in spelling like `v1.2` and `1.2.0` are equal
+    pub fn cmp_precedence(&self, other: &Self) -> Ordering {
+        match (self.semver(), other.semver()) {
+            (Some(a), Some(b)) => a
+                .cmp_precedence(b)
+                .then_with(|| compare_segments(a.build.as_str(), b.build.as_str())),
+            _ => compare_debian(&self.debian(), &other.debian()),
+        }
+    }
+}
+
+/// [`FlexibleVersion::cmp_precedence`], ties that only differ in spelling
+/// are broken deterministically, so that the ordering stays consistent with `Eq`
+impl Ord for FlexibleVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_precedence(other)
+            .then_with(|| self.rank().cmp(&other.rank()))
+            .then_with(|| self.to_string().cmp(&other.to_string()))
+    }
+}
+
+/// how big the step between two versions is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Major,
+    Minor,
+    Patch,
+    /// pre-releases, revisions, or versions that are not semver
+    Other,
+}
+
+impl Change {
+    pub fn between(from: &FlexibleVersion, to: &FlexibleVersion) -> Self {
+        match (from.semver(), to.semver()) {
+            (Some(a), Some(b)) if a.major != b.major => Self::Major,
+            (Some(a), Some(b)) if a.minor != b.minor => Self::Minor,
+            (Some(a), Some(b)) if a.patch != b.patch => Self::Patch,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Major => write!(f, "major"),
+            Self::Minor => write!(f, "minor"),
+            Self::Patch => write!(f, "patch"),
+            Self::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl PartialOrd for FlexibleVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// compare two versions like dpkg does, `[epoch:]upstream[-revision]`
+///
+/// e.g. `1:2.3-4ubuntu1`, `2024.01.15`, `1.0~rc1` (sorts before `1.0`)
+pub fn compare_debian(a: &str, b: &str) -> Ordering {
+    let (epoch_a, upstream_a, revision_a) = split_debian(a);
+    let (epoch_b, upstream_b, revision_b) = split_debian(b);
+    epoch_a
+        .cmp(&epoch_b)
+        .then_with(|| compare_segments(upstream_a, upstream_b))
+        .then_with(|| compare_segments(revision_a, revision_b))
+}
+
+fn split_debian(s: &str) -> (u64, &str, &str) {
+    let (epoch, rest) = match s.split_once(':') {
+        Some((epoch, rest)) if epoch.chars().all(|c| c.is_ascii_digit()) && !epoch.is_empty() => {
+            (epoch.parse().unwrap_or(u64::MAX), rest)
+        }
+        _ => (0, s),
+    };
+    match rest.rsplit_once('-') {
+        Some((upstream, revision)) => (epoch, upstream, revision),
+        None => (epoch, rest, ""),
+    }
+}
+
+/// weight of a char in the non-digit part, `~` sorts before everything, even the end
+fn weight(c: Option<char>) -> i64 {
+    match c {
+        Some('~') => -1,
+        None => 0,
+        Some(c) if c.is_ascii_alphabetic() => c as i64,
+        Some(c) => c as i64 + 256,
+    }
+}
+
+/// dpkg's `verrevcmp`: alternate between non-digit runs compared by `weight`,
+/// and digit runs compared numerically
+fn compare_segments(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        if a.peek().is_none() && b.peek().is_none() {
+            return Ordering::Equal;
+        }
+        // 非数字部分
+        loop {
+            let (ca, cb) = (
+                a.peek().copied().filter(|c| !c.is_ascii_digit()),
+                b.peek().copied().filter(|c| !c.is_ascii_digit()),
+            );
+            if ca.is_none() && cb.is_none() {
+                break;
+            }
+            match weight(ca).cmp(&weight(cb)) {
+                Ordering::Equal => {
+                    a.next();
+                    b.next();
+                }
+                ord => return ord,
+            }
+        }
+        // 数字部分，忽略前导零后按长度再按字典序比较，避免溢出
+        let digits = |it: &mut std::iter::Peekable<std::str::Chars>| {
+            let mut s = String::new();
+            while let Some(c) = it.next_if(|c| c.is_ascii_digit()) {
+                s.push(c);
+            }
+            s.trim_start_matches('0').to_string()
+        };
+        let (da, db) = (digits(&mut a), digits(&mut b));
+        match da.len().cmp(&db.len()).then_with(|| da.cmp(&db)) {
+            Ordering::Equal => {}
+            ord => return ord,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// `=1.2.3` or `==1.2`, only the version itself
+    Exact,
+    /// `=1.2` written without all its parts, also matches versions starting with it, like `1.2.7`
+    Prefix,
+    NotExact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    /// `^1.2`, compatible updates: `>=1.2, <2`
+    Caret,
+    /// `~1.2`, patch updates: `>=1.2, <1.3`
+    Tilde,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparator {
+    op: Op,
+    version: FlexibleVersion,
+}
+
+/// a set of comparators that must all match, e.g. `>=1.2, <2`
+///
+/// a bare version means an exact match, or a prefix match if it is partial like `1.2`,
+/// `*` matches everything
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct VersionRange {
+    comparators: Vec<Comparator>,
+}
+
+impl FromStr for VersionRange {
+    type Err = Report;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut comparators = Vec::new();
+        for part in s.split(',').map(str::trim) {
+            if part == "*" {
+                continue;
+            }
+            let (op, rest) = [
+                ("==", Op::Exact),
+                (">=", Op::GreaterEq),
+                ("<=", Op::LessEq),
+                ("!=", Op::NotExact),
+                (">", Op::Greater),
+                ("<", Op::Less),
+                ("=", Op::Exact),
+                ("^", Op::Caret),
+                ("~", Op::Tilde),
+            ]
+            .into_iter()
+            .find_map(|(prefix, op)| part.strip_prefix(prefix).map(|rest| (op, rest)))
+            .unwrap_or((Op::Exact, part));
+            let rest = rest.trim();
+            if rest.is_empty() {
+                bail!("missing version in `{}`", s);
+            }
+            if rest.contains(char::is_whitespace) {
+                bail!("`{}` is not a version, separate comparators with `,`", rest);
+            }
+            let version = FlexibleVersion::parse(rest);
+            let op = match op {
+                Op::Exact if !part.starts_with("==") && is_partial(rest) => Op::Prefix,
+                Op::Exact | Op::NotExact => op,
+                Op::Caret | Op::Tilde => {
+                    numeric_parts(&version.written())?;
+                    op
+                }
+                _ => {
+                    if !rest
+                        .trim_start_matches(['v', 'V'])
+                        .starts_with(|c: char| c.is_ascii_digit())
+                    {
+                        bail!("`{}` does not start with a number", rest);
+                    }
+                    op
+                }
+            };
+            comparators.push(Comparator { op, version });
+        }
+        Ok(Self { comparators })
+    }
+}
+
+impl TryFrom<String> for VersionRange {
+    type Error = Report;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<VersionRange> for String {
+    fn from(range: VersionRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl std::fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.comparators.is_empty() {
+            return write!(f, "*");
+        }
+        let parts: Vec<String> = self
+            .comparators
+            .iter()
+            .map(|c| {
+                let op = match c.op {
+                    // 不写成 `==` 的话再读回来就成了前缀匹配
+                    Op::Exact if is_partial(&c.version.written()) => "==",
+                    Op::Exact | Op::Prefix => "=",
+                    Op::NotExact => "!=",
+                    Op::Greater => ">",
+                    Op::GreaterEq => ">=",
+                    Op::Less => "<",
+                    Op::LessEq => "<=",
+                    Op::Caret => "^",
+                    Op::Tilde => "~",
+                };
+                format!("{}{}", op, c.version)
+            })
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// whether `s` leaves out parts of a version, like `1.2` or `v1`
+fn is_partial(s: &str) -> bool {
+    let s = s.strip_prefix(['v', 'V']).unwrap_or(s);
+    s.split('.').count() < 3
+        && s.split('.')
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// leading numeric components, `1.2.3-r1` gives `[1, 2, 3]`
+fn numeric_parts(s: &str) -> color_eyre::Result<Vec<u64>> {
+    let parts: Vec<u64> = s
+        .split(['.', '-', '+'])
+        .map_while(|p| p.parse().ok())
+        .collect();
+    if parts.is_empty() {
+        return Err(eyre!("`{}` does not start with a number", s));
+    }
+    Ok(parts)
+}
+
+/// the smallest version above every version matched by `^parts` or `~parts`
+fn bump(parts: &[u64], caret: bool) -> FlexibleVersion {
+    let index = if caret {
+        // ^0.2 只允许 0.2.x，^0.0.3 只允许 0.0.3
+        parts
+            .iter()
+            .position(|p| *p != 0)
+            .unwrap_or(parts.len() - 1)
+    } else {
+        parts.len().min(2) - 1
+    };
+    let mut upper = parts[..=index].to_vec();
+    upper[index] += 1;
+    let upper: Vec<String> = upper.iter().map(|p| p.to_string()).collect();
+    FlexibleVersion::Raw(upper.join("."))
+}
+
+impl Comparator {
+    fn matches(&self, version: &FlexibleVersion) -> bool {
+        let ord = compare_debian(&version.debian(), &self.version.debian());
+        match self.op {
+            Op::Exact => ord == Ordering::Equal,
+            Op::Prefix => self.is_prefix_of(version),
+            // `!=1.2` 排除 `=1.2` 匹配的所有版本
+            Op::NotExact if is_partial(&self.version.written()) => !self.is_prefix_of(version),
+            Op::NotExact => ord != Ordering::Equal,
+            Op::Greater => ord == Ordering::Greater,
+            Op::GreaterEq => ord != Ordering::Less,
+            Op::Less => ord == Ordering::Less,
+            Op::LessEq => ord != Ordering::Greater,
+            Op::Caret | Op::Tilde => {
+                let parts = numeric_parts(&self.version.written()).unwrap_or_default();
+                if ord == Ordering::Less || parts.is_empty() {
+                    return false;
+                }
+                let upper = bump(&parts, self.op == Op::Caret);
+                compare_debian(&version.debian(), &upper.debian()) == Ordering::Less
+            }
+        }
+    }
+
+    fn is_prefix_of(&self, version: &FlexibleVersion) -> bool {
+        let (v, c) = (version.written(), self.version.written());
+        compare_debian(&version.debian(), &self.version.debian()) == Ordering::Equal
+            || v.strip_prefix(&c)
+                .is_some_and(|rest| rest.starts_with(['.', '-', '+', '~']))
+    }
+}
+
+impl VersionRange {
+    /// only `version` itself
+    pub fn exact(version: FlexibleVersion) -> Self {
+        Self {
+            comparators: vec![Comparator {
+                op: Op::Exact,
+                version,
+            }],
+        }
+    }
+
+    /// the version if the range is a single exact one, e.g. `1.2.3` or `=1.2`
+    pub fn as_exact(&self) -> Option<&FlexibleVersion> {
+        match self.comparators.as_slice() {
+            [
+                Comparator {
+                    op: Op::Exact | Op::Prefix,
+                    version,
+                },
+            ] => Some(version),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, version: &FlexibleVersion) -> bool {
+        self.comparators.iter().all(|c| c.matches(version))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn v(s: &str) -> FlexibleVersion {
+        FlexibleVersion::parse(s)
+    }
+
+    #[test]
+    fn test_compare_debian() {
+        use Ordering::*;
+        for (a, b, ord) in [
+            ("1.0", "1.0", Equal),
+            ("1.0", "1.00", Equal),
+            ("1.2", "1.10", Less),
+            ("1.0~rc1", "1.0", Less),
+            ("1.0", "1.0a", Less),
+            ("1.0a", "1.0.1", Less),
+            ("1:1.0", "2.0", Greater),
+            ("2.3-4ubuntu1", "2.3-4ubuntu2", Less),
+            ("2.3-4", "2.3-10", Less),
+            ("1:2.3-4ubuntu1", "1:2.3-4", Greater),
+            ("2024.01.15", "2024.1.16", Less),
+            ("2024.01.15", "2023.12.31", Greater),
+        ] {
+            assert_eq!(ord, compare_debian(a, b), "{} vs {}", a, b);
+            assert_eq!(ord.reverse(), compare_debian(b, a), "{} vs {}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        for (input, normalized) in [
+            ("v1.2", "1.2.0"),
+            ("1.2", "1.2.0"),
+            ("V3", "3.0.0"),
+            ("1.2.3.4", "1.2.3+4"),
+            ("1.2.3-r1", "1.2.3+r1"),
+            ("1.2.3-p2+linux", "1.2.3+p2.linux"),
+            ("1.2-rc1", "1.2.0-rc1"),
+            ("2024.01.15", "2024.1.15"),
+        ] {
+            let version = v(input);
+            assert_eq!(
+                Some(normalized),
+                version.semver().map(|v| v.to_string()).as_deref()
+            );
+            assert_eq!(input, version.to_string());
+        }
+        assert!(matches!(v("1.2.3"), FlexibleVersion::Semantic(_)));
+        for raw in [
+            "1:2.3-4ubuntu1",
+            "1.2.3.4.5",
+            "latest",
+            "1.2-foo",
+            "1.2-2",
+            "v",
+        ] {
+            assert_eq!(FlexibleVersion::Raw(raw.into()), v(raw), "{}", raw);
+        }
+
+        assert!(v("1.2.3-r1") > v("1.2.3"));
+        assert!(v("1.2.3-r10") > v("1.2.3-r2"));
+        assert!(v("1.2.3.10") > v("1.2.3.9"));
+        assert!(v("v1.10") > v("1.9.1"));
+        assert!(v("1.2-rc1") < v("1.2"));
+    }
+
+    #[test]
+    fn test_serde() -> color_eyre::Result<()> {
+        for version in [v("1.2.3"), v("v1.2"), v("1:2.3-4ubuntu1")] {
+            let json = serde_json::to_string(&version)?;
+            assert_eq!(version, serde_json::from_str(&json)?);
+        }
+        // 旧版本的拼写和没有规范化的 Raw 也能读
+        let old: FlexibleVersion = serde_json::from_str(r#"{"Sematic":"1.21.0"}"#)?;
+        assert_eq!(v("1.21.0"), old);
+        let raw: FlexibleVersion = serde_json::from_str(r#"{"Raw":"v1.2"}"#)?;
+        assert_eq!(Some(&Version::new(1, 2, 0)), raw.semver());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut versions = [
+            v("2.0.0"),
+            v("1.0.0"),
+            v("1.0.0-alpha.1"),
+            v("1.0.0-alpha"),
+            v("1:0.1"),
+            v("1.5"),
+            v("1.0.0-beta"),
+        ];
+        versions.sort();
+        let sorted: Vec<String> = versions.iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            vec![
+                "1.0.0-alpha",
+                "1.0.0-alpha.1",
+                "1.0.0-beta",
+                "1.0.0",
+                "1.5",
+                "2.0.0",
+                "1:0.1"
+            ],
+            sorted
+        );
+        // 拼写不同但 dpkg 认为相等的版本也要有确定的顺序
+        assert_eq!(Ordering::Less, v("1.0").cmp(&v("1.00")));
+        assert_eq!(
+            Ordering::Less,
+            v("1.0.0").cmp(&FlexibleVersion::Raw("1.0.0".into()))
+        );
+    }
+
+    #[test]
+    fn test_change() {
+        assert_eq!(Change::Major, Change::between(&v("1.9.0"), &v("2.0.0")));
+        assert_eq!(Change::Minor, Change::between(&v("v1.2"), &v("1.3.0")));
+        assert_eq!(Change::Patch, Change::between(&v("1.2.3"), &v("1.2.4")));
+        assert_eq!(Change::Other, Change::between(&v("1.2.3"), &v("1.2.3-r1")));
+        assert_eq!(Change::Other, Change::between(&v("1:2.3-4"), &v("1:2.3-5")));
+        assert_eq!(Ordering::Equal, v("v1.2").cmp_precedence(&v("1.2.0")));
+    }
+
+    #[test]
+    fn test_range() -> color_eyre::Result<()> {
+        let range: VersionRange = ">=1.2, <2".parse()?;
+        assert!(range.matches(&v("1.2.0")));
+        assert!(range.matches(&v("1.9.9")));
+        assert!(range.matches(&v("1.10")));
+        assert!(!range.matches(&v("2.0.0")));
+        assert!(!range.matches(&v("1.1.9")));
+
+        let exact: VersionRange = "14.1".parse()?;
+        assert!(exact.matches(&v("14.1.0")));
+        assert!(exact.matches(&v("14.1")));
+        assert!(!exact.matches(&v("14.10.0")));
+        assert_eq!(Some(&v("14.1")), exact.as_exact());
+        assert_eq!(None, range.as_exact());
+        // 完整的版本和 `exact` 只匹配自己
+        let full: VersionRange = "=14.1.0".parse()?;
+        assert!(full.matches(&v("14.1.0")));
+        assert!(!full.matches(&v("14.1.0-2")));
+        let pinned = VersionRange::exact(v("14.1.0"));
+        assert!(!pinned.matches(&v("14.1.0-2")));
+        assert!(!pinned.matches(&v("14.1.0.1")));
+        let partial = VersionRange::exact(v("14.1"));
+        assert!(!partial.matches(&v("14.1.7")));
+        assert_eq!("==14.1", partial.to_string());
+        assert_eq!(partial, partial.to_string().parse()?);
+        assert!(!"!=14.1".parse::<VersionRange>()?.matches(&v("14.1.7")));
+        assert!("!=14.1.0".parse::<VersionRange>()?.matches(&v("14.1.0-2")));
+
+        let caret: VersionRange = "^1.2".parse()?;
+        assert!(caret.matches(&v("1.9.0")));
+        assert!(!caret.matches(&v("2.0.0")));
+        let caret_zero: VersionRange = "^0.2.1".parse()?;
+        assert!(caret_zero.matches(&v("0.2.9")));
+        assert!(!caret_zero.matches(&v("0.3.0")));
+        let tilde: VersionRange = "~1.2".parse()?;
+        assert!(tilde.matches(&v("1.2.9")));
+        assert!(!tilde.matches(&v("1.3.0")));
+
+        let debian: VersionRange = ">=1:2.3, <1:2.4".parse()?;
+        assert!(debian.matches(&v("1:2.3-4ubuntu1")));
+        assert!(!debian.matches(&v("2.3-4ubuntu1")));
+
+        assert!("*".parse::<VersionRange>()?.matches(&v("anything")));
+        assert_eq!(">=1.2, <2", range.to_string());
+        assert!(">=".parse::<VersionRange>().is_err());
+        assert!("^abc".parse::<VersionRange>().is_err());
+        assert!(">=x".parse::<VersionRange>().is_err());
+        assert!(">=1.2 <2".parse::<VersionRange>().is_err());
+        Ok(())
+    }
+}
@@ -33,17 +33,349 @@
 //     println!("time elapsed: {:.2?}", end - start);
 // }
 
-use std::env;
-
 use clap::Parser;
+use color_eyre::{Result as Res, eyre::bail};
+use colored::Colorize;
 
-use crate::core::cli::Cli;
+use crate::core::{
+    cli::{Cli, Commands, LogCommands, ManifestCommands, RemoteCommands, TagCommands},
+    config::Config,
+    data::{DataManager, FlexibleVersion, Query, RecordData},
+    detect, edit, fio,
+    journal::Journal,
+    lock::Lockfile,
+    manifest::Manifest,
+    oplog::{self, OpLog},
+    outdated,
+    parser::Listing,
+    profile,
+    service::{Command, Manager, TagCommand},
+    source::Source,
+    verify,
+};
 
 mod core;
 
-fn main() {
-    let args = env::args();
-    println!("args:{:?}", args);
+fn main() -> Res<()> {
+    color_eyre::install()?;
+    let cli = Cli::parse();
+
+    let config = Config::load(&fio::get_config_path()?)?;
+    let data = DataManager::new(&fio::get_db_path()?)?;
+    let log = OpLog::new(&fio::get_oplog_dir()?);
+    let journal = Journal::new(&fio::get_oplog_dir()?);
+    let mut manager = Manager::new(config, data, log, journal, &fio::get_sync_dir()?);
+
+    run(cli.command, &mut manager)
+}
+
+fn run(command: Commands, manager: &mut Manager) -> Res<()> {
+    match command {
+        Commands::Record {
+            name,
+            source,
+            version,
+            tags,
+            description,
+            installed,
+        } => manager.execute(Command::Record(RecordData {
+            name,
+            source,
+            version: version.as_deref().map(FlexibleVersion::parse),
+            installation_date: installed,
+            tags,
+            description,
+            ..Default::default()
+        })),
+        Commands::Install {
+            package,
+            manager: source,
+            sha256,
+        } => {
+            let mut record = match source {
+                Some(source) => RecordData {
+                    name: package,
+                    source: Some(source),
+                    ..Default::default()
+                },
+                None => package.parse::<Source>()?.record(),
+            };
+            record.sha256 = sha256;
+            manager.execute(Command::Install(record))
+        }
+        Commands::Remove { id, record_only } => {
+            manager.execute(Command::Remove { id, record_only })
+        }
+        Commands::List { select, all_hosts } => {
+            let query = select.query(&manager.config().profile)?;
+            // 默认只列出本机的记录，选了主机时列出那台的
+            let everywhere = all_hosts || query.host.is_some();
+            for record in manager.records() {
+                if !everywhere && !manager.is_here(record) {
+                    continue;
+                }
+                if query.is_empty() || query.matches(record) {
+                    match (&record.host, everywhere) {
+                        (Some(host), true) => println!("{}  @{}", record, host.name),
+                        _ => println!("{}", record),
+                    }
+                }
+            }
+            Ok(())
+        }
+        Commands::Apply {
+            jobs,
+            locked: Some(path),
+            ..
+        } => manager.execute(Command::ApplyLocked {
+            lockfile: Lockfile::load(&path)?,
+            jobs,
+        }),
+        Commands::Apply {
+            jobs,
+            file,
+            prune,
+            mut profiles,
+            ..
+        } => {
+            let config = &manager.config().profile;
+            // 没有指定清单或 profile 时，按主机名选 profile
+            if file.is_none() && profiles.is_empty() {
+                let hostname = &manager.host().name;
+                profiles = profile::for_host(config, hostname);
+                if !profiles.is_empty() {
+                    println!("using profiles {} for `{}`", profiles.join(", "), hostname);
+                }
+            }
+            let query = Query {
+                profile: match profiles.is_empty() {
+                    true => None,
+                    false => Some(profile::members(config, &profiles)?),
+                },
+                ..Default::default()
+            };
+            manager.execute(Command::Apply {
+                query,
+                jobs,
+                manifest: file.as_deref().map(Manifest::load).transpose()?,
+                prune,
+            })
+        }
+        Commands::Lock { output } => {
+            let lockfile = manager.lock()?;
+            lockfile.save(&output)?;
+            println!(
+                "locked {} packages in `{}`",
+                lockfile.packages.len(),
+                output.display()
+            );
+            Ok(())
+        }
+        Commands::Manifest {
+            command: ManifestCommands::Generate { output },
+        } => {
+            let records = manager.records();
+            for record in records.iter().filter(|r| r.source.is_none()) {
+                eprintln!("skipped `{}`, it has no source", record.name);
+            }
+            let manifest = Manifest::from_records(&records).to_string();
+            match output {
+                Some(path) => std::fs::write(path, manifest)?,
+                None => print!("{}", manifest),
+            }
+            Ok(())
+        }
+        Commands::Outdated { select } => {
+            let outdated = manager.outdated(&select.query(&manager.config().profile)?)?;
+            if outdated.is_empty() {
+                println!("everything is up to date");
+            }
+            for row in outdated::table(&outdated) {
+                println!("{}", row);
+            }
+            Ok(())
+        }
+        Commands::Verify { select } => {
+            let verified = manager.verify(&select.query(&manager.config().profile)?)?;
+            for row in verify::table(&verified) {
+                println!("{}", row);
+            }
+            let broken = verified.iter().filter(|v| v.status.is_broken()).count();
+            if broken > 0 {
+                bail!("{} of {} installs are broken", broken, verified.len());
+            }
+            Ok(())
+        }
+        Commands::Upgrade { select, jobs } => manager.execute(Command::Upgrade {
+            query: select.query(&manager.config().profile)?,
+            jobs,
+        }),
+        Commands::Pin { record, range } => {
+            let id = manager.resolve(&record)?.id;
+            manager.execute(Command::Pin { id, range })
+        }
+        Commands::Unpin { record } => {
+            let id = manager.resolve(&record)?.id;
+            manager.execute(Command::Unpin { id })
+        }
+        Commands::Search {
+            manager: source,
+            term,
+        } => {
+            print_listing(manager.search(&source, &term)?);
+            Ok(())
+        }
+        Commands::Info { record } => {
+            print_listing(manager.info(&record)?);
+            Ok(())
+        }
+        Commands::Installed { manager: source } => {
+            for package in manager.installed(&source)? {
+                let recorded = manager
+                    .records()
+                    .iter()
+                    .any(|r| r.name == package.name && r.source.as_ref() == Some(&source));
+                match recorded {
+                    true => println!("  {}", package),
+                    false => println!("{}", format!("+ {}", package).green()),
+                }
+            }
+            Ok(())
+        }
+        Commands::Managers => {
+            for row in detect::table(&detect::detect(&manager.config().manager)) {
+                println!("{}", row);
+            }
+            Ok(())
+        }
+        Commands::Clean { managers } => manager.execute(Command::Clean { managers }),
+        Commands::Refresh { managers } => manager.execute(Command::Refresh { managers }),
+        Commands::Clear => manager.execute(Command::Clear),
+        Commands::Remote {
+            command: Some(RemoteCommands::Add { url }),
+        } => manager.add_remote(&url),
+        Commands::Remote { command: None } => {
+            match manager.remote()? {
+                Some(url) => println!("{}", url),
+                None => println!("no remote, add one with `fmn remote add <url>`"),
+            }
+            Ok(())
+        }
+        Commands::Pull => manager.execute(Command::Pull),
+        Commands::Push => manager.execute(Command::Push),
+        Commands::Undo { inverse } => manager.execute(Command::Undo { inverse }),
+        Commands::Tag { command } => match command {
+            TagCommands::Add { tag, select } => manager.execute(Command::Tag(TagCommand::Add {
+                tag,
+                query: select.query(&manager.config().profile)?,
+            })),
+            TagCommands::Rm { tag, select } => manager.execute(Command::Tag(TagCommand::Remove {
+                tag,
+                query: select.query(&manager.config().profile)?,
+            })),
+            TagCommands::Rename { from, to, select } => {
+                manager.execute(Command::Tag(TagCommand::Rename {
+                    from,
+                    to,
+                    query: select.query(&manager.config().profile)?,
+                }))
+            }
+            TagCommands::List => {
+                for (tag, count) in manager.tag_counts() {
+                    println!("{:>4}  {}", count, tag);
+                }
+                Ok(())
+            }
+        },
+        Commands::Edit {
+            record,
+            format,
+            yes,
+        } => {
+            let before = manager.resolve(&record)?.clone();
+            let Some(after) = edit::edit(&before, format)? else {
+                println!("nothing changed");
+                return Ok(());
+            };
+            update(manager, &before, after, format, yes)
+        }
+        Commands::Set { record, fields } => {
+            let before = manager.resolve(&record)?.clone();
+            let mut after = before.clone();
+            for (key, value) in &fields {
+                after.set(key, value)?;
+            }
+            update(manager, &before, after, edit::Format::Toml, true)
+        }
+        Commands::History { name } => {
+            let history = manager.journal().history(&name)?;
+            if history.is_empty() {
+                println!("no history for `{}`", name);
+            }
+            for entry in history {
+                println!("{}", entry);
+            }
+            Ok(())
+        }
+        Commands::Log {
+            command: Some(LogCommands::Show { id }),
+            ..
+        } => {
+            let op = manager.log().get(id)?;
+            println!("{}", op);
+            if let Some(error) = &op.error {
+                println!("error: {}", error);
+            }
+            let (stdout, stderr) = manager.log().output(id)?;
+            println!("--- stdout ---\n{}", stdout.trim_end());
+            println!("--- stderr ---\n{}", stderr.trim_end());
+            Ok(())
+        }
+        Commands::Log {
+            command: None,
+            filter,
+        } => {
+            let filter = oplog::Filter {
+                manager: filter.manager,
+                record: filter.record,
+                failed: filter.failed,
+                limit: filter.limit,
+            };
+            for op in manager.log().filter(&filter)? {
+                println!("{}", op);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn print_listing(listing: Listing) {
+    match listing {
+        Listing::Packages(packages) => {
+            for package in packages {
+                println!("{}", package);
+            }
+        }
+        Listing::Raw(output) => print!("{}", output),
+    }
+}
 
-    let _cli = Cli::parse();
+/// show what changes and apply it, asks first unless `yes`
+fn update(
+    manager: &mut Manager,
+    before: &RecordData,
+    after: RecordData,
+    format: edit::Format,
+    yes: bool,
+) -> Res<()> {
+    let (old, new) = (format.render(before)?, format.render(&after)?);
+    for line in edit::diff_lines(&old, &new) {
+        if !matches!(line, edit::DiffLine::Same(_)) {
+            println!("{}", line);
+        }
+    }
+    if yes || edit::confirm("apply these changes?")? {
+        manager.execute(Command::Update(after))?;
+    }
+    Ok(())
 }